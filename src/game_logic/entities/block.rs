@@ -1,18 +1,107 @@
+use std::f32::consts::PI;
 use std::time::Duration;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy_rapier2d::control::KinematicCharacterControllerOutput;
-use bevy_rapier2d::prelude::{Collider, RigidBody};
-use crate::game_logic::entities::champi::{Champi, ChampiFactory};
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::entities::champi::{Champi, spawn_champi};
+use crate::game_logic::entities::coin::{CoinCollected, spawn_popped_coin};
+use crate::game_logic::entities::enemy::{Enemy, knock_out};
+use crate::game_logic::entities::mario::{Direction, HeadBump, Player, PowerUp, Score};
+use crate::game_logic::world::level::{BlockContents, BlockKind, LevelEntity};
+use crate::game_logic::world::physics::KinematicVelocity;
+use crate::game_logic::world::platform::one_way_solid_group;
 use crate::rendering::animation::Animation;
-use crate::rendering::sprite_manager::{SPRITE_TILE_HEIGHT, SPRITE_TILE_WIDTH};
+use crate::rendering::sprite_manager::{ItemAtlases, SPRITE_IDX_BLOCK_OPENED, SPRITE_IDX_BRICK, SPRITE_IDX_DEBRIS, SPRITE_TILE_HEIGHT, SPRITE_TILE_WIDTH};
+
+// Blocks are drawn in front of whatever comes out of them, see Emerging
+pub const BLOCK_Z: f32 = 0.2;
+
+const BUMP_HEIGHT: f32 = 12.0;
+const BUMP_DURATION: Duration = Duration::from_millis(200);
+const BUMP_SENSOR_HEIGHT: f32 = 8.0;
+const BUMP_KNOCK_VELOCITY: f32 = 500.0;
+const SCORE_ENEMY: u32 = 200;
+const MULTI_COIN_DURATION: Duration = Duration::from_millis(4000);
+
+// Hidden blocks collide with nothing, until a rising player is right below one, and then only with
+// this player, see resolve_hidden_blocks
+const COLLISION_GROUPS_HIDDEN: CollisionGroups = CollisionGroups::new(Group::NONE, Group::ALL);
+// How far the head of the player can go past the bottom of a hidden block and still hit it
+const HIDDEN_HIT_TOLERANCE: f32 = 8.0;
+
+const DEBRIS_GRAVITY: f32 = 2400.0;
+const DEBRIS_LIFETIME: Duration = Duration::from_millis(1200);
+// Where each of the four debris starts from the center of the brick, and how it is thrown
+const DEBRIS: &[(Vec2, Vec2)] = &[
+    (Vec2::new(-8.0, 8.0), Vec2::new(-180.0, 700.0)),
+    (Vec2::new(8.0, 8.0), Vec2::new(180.0, 700.0)),
+    (Vec2::new(-8.0, -8.0), Vec2::new(-140.0, 450.0)),
+    (Vec2::new(8.0, -8.0), Vec2::new(140.0, 450.0)),
+];
 
 #[derive(Component)]
 pub struct Opened(f32);
 
 #[derive(Component)]
 pub struct Block {
-    id: i32,
-    pub opened: bool,
+    pub kind: BlockKind,
+    pub contents: BlockContents,
+}
+
+impl Block {
+    fn breakable(&self) -> bool {
+        self.kind == BlockKind::Brick && self.contents == BlockContents::Empty
+    }
+}
+
+// A hidden block that was hit once, seen and solid from then on even while it still has contents
+#[derive(Component)]
+pub struct Revealed;
+
+// Started by the first hit on a multi-coin block, which gives its last coin once it ends
+#[derive(Component)]
+pub struct MultiCoinTimer(Timer);
+
+// Sent once for every block hit from below, by is whoever hit it
+#[derive(Event)]
+pub struct BlockHit {
+    pub block: Entity,
+    pub by: Entity,
+}
+
+// A block going up and back down after a hit, knocking out whatever stands on it
+#[derive(Component)]
+pub struct Bumped {
+    by: Entity,
+    rest_y: f32,
+    timer: Timer,
+    knocked: Vec<Entity>,
+}
+
+impl Bumped {
+    fn new(by: Entity, rest_y: f32) -> Self {
+        Self {
+            by,
+            rest_y,
+            timer: Timer::new(BUMP_DURATION, TimerMode::Once),
+            knocked: vec![],
+        }
+    }
+}
+
+// Whatever can stand on a block and be knocked out by a hit from below
+#[derive(SystemParam)]
+pub struct StandingOnBlock<'w, 's> {
+    enemies: Query<'w, 's, &'static Transform, (With<Enemy>, Without<Block>)>,
+    champis: Query<'w, 's, (&'static Transform, &'static mut KinematicVelocity, &'static mut Direction), (With<Champi>, Without<Block>)>,
+    scores: Query<'w, 's, &'static mut Score>,
+}
+
+// A piece of a broken brick, flying away without colliding with anything
+#[derive(Component)]
+pub struct Debris {
+    velocity: Vec2,
+    lifetime: Timer,
 }
 
 #[derive(Bundle)]
@@ -21,25 +110,36 @@ pub struct BlockFactory {
     sprite_bundle: SpriteSheetBundle,
     body: RigidBody,
     collider: Collider,
+    collision_groups: CollisionGroups,
     animation: Animation
 }
 
 impl BlockFactory {
-    pub fn new(id: i32, texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
+    pub fn new(kind: BlockKind, contents: BlockContents, item_atlases: &ItemAtlases, x: f32, y: f32) -> Self {
         const CYCLE_DELAY: Duration = Duration::from_millis(500);
 
         const SPRITE_IDX_ANIM: &[usize] = &[0, 1, 2, 3];
+        const SPRITE_IDX_BRICK_ANIM: &[usize] = &[SPRITE_IDX_BRICK];
+        const SPRITE_IDX_HIDDEN_ANIM: &[usize] = &[SPRITE_IDX_BLOCK_OPENED];
+
+        let (texture_atlas, sprites) = match kind {
+            BlockKind::Question => (item_atlases.blocks.clone(), SPRITE_IDX_ANIM),
+            BlockKind::Brick => (item_atlases.items.clone(), SPRITE_IDX_BRICK_ANIM),
+            BlockKind::Hidden => (item_atlases.blocks.clone(), SPRITE_IDX_HIDDEN_ANIM),
+        };
+
         Self {
             sprite_bundle: SpriteSheetBundle {
-                sprite: TextureAtlasSprite::new(0),
+                sprite: TextureAtlasSprite::new(sprites[0]),
                 texture_atlas,
+                visibility: if kind == BlockKind::Hidden { Visibility::Hidden } else { Visibility::Inherited },
                 transform: Transform {
                     scale: Vec3::new(
                         2.0,
                         2.0,
                         1.0,
                     ),
-                    translation: Vec3::new(x, y, 0.0),
+                    translation: Vec3::new(x, y, BLOCK_Z),
                     ..Default::default()
                 },
                 ..Default::default()
@@ -49,38 +149,332 @@ impl BlockFactory {
                 SPRITE_TILE_WIDTH / 2.0,
                 SPRITE_TILE_HEIGHT / 2.0,
             ),
+            collision_groups: if kind == BlockKind::Hidden { COLLISION_GROUPS_HIDDEN } else { CollisionGroups::default() },
             block: Block {
-                id,
-                opened: false
+                kind,
+                contents,
             },
-            animation: Animation::new(SPRITE_IDX_ANIM, CYCLE_DELAY),
+            animation: Animation::new(sprites, CYCLE_DELAY),
         }
     }
 }
 
 pub fn detect_collision_from_below_on_block(
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut Block), Without<Opened>>,
-    mut character_controller_outputs: Query<&mut KinematicCharacterControllerOutput>,
-    mut champi_query: Query<&mut Champi>,
+    mut head_bumps: EventReader<HeadBump>,
+    players: Query<&Transform>,
+    blocks: Query<&Transform, With<Block>>,
+    mut block_hits: EventWriter<BlockHit>,
 ) {
-    if champi_query.is_empty() {
-        return;
+    // a head touching two blocks at once only hits the one closest to its middle
+    let mut hits: Vec<(Entity, Entity, f32)> = vec![];
+
+    for head_bump in head_bumps.iter() {
+        let Ok(block_transform) = blocks.get(head_bump.entity) else { continue };
+        let Ok(player_transform) = players.get(head_bump.player) else { continue };
+
+        let distance = (block_transform.translation.x - player_transform.translation.x).abs();
+
+        match hits.iter_mut().find(|(by, _, _)| *by == head_bump.player) {
+            Some(hit) if distance < hit.2 => *hit = (head_bump.player, head_bump.entity, distance),
+            Some(_) => {}
+            None => hits.push((head_bump.player, head_bump.entity, distance)),
+        }
+    }
+
+    // a block hit by both players at once is only hit once
+    let mut hit_blocks = vec![];
+
+    for (by, block, _) in hits {
+        if hit_blocks.contains(&block) {
+            continue;
+        }
+        hit_blocks.push(block);
+        block_hits.send(BlockHit { block, by });
     }
+}
+
+pub fn open_block(
+    mut commands: Commands,
+    item_atlases: Res<ItemAtlases>,
+    rapier_context: Res<RapierContext>,
+    mut standing: StandingOnBlock,
+    mut block_hits: EventReader<BlockHit>,
+    mut coins_collected: EventWriter<CoinCollected>,
+    players: Query<&PowerUp>,
+    mut query: Query<(&mut Block, &Transform, Option<&Bumped>, Option<&MultiCoinTimer>), Without<Opened>>,
+) {
+    for block_hit in block_hits.iter() {
+        let Ok((mut block, transform, bumped, multi_coin_timer)) = query.get_mut(block_hit.block) else { continue };
 
-    for(mut champi) in champi_query.iter_mut() {
-        for (entity, mut block) in query.iter_mut() {
-            for mut output in character_controller_outputs.iter_mut() {
-                for collision in &output.collisions {
-                    if collision.toi.normal1.y == -1.0 {
-                        commands.entity(entity).insert(Opened(0.0));
-                        if block.id == champi.block_id {
-                            champi.visible = true;
-                            champi.upcoming = true;
-                        }
+        // a block hit again while still moving goes back to where it rested
+        let x = transform.translation.x;
+        let y = bumped.map_or(transform.translation.y, |bumped| bumped.rest_y);
+
+        // small Mario only bumps bricks
+        if block.breakable() && players.get(block_hit.by).map_or(false, |power_up| *power_up != PowerUp::Small) {
+            // what stands on a broken brick is knocked out just like on a bumped one
+            let mut bumped = Bumped::new(block_hit.by, y);
+            knock_out_standing(&mut commands, &rapier_context, block_hit.block, Vec2::new(x, y), &mut bumped, &mut standing);
+
+            commands.entity(block_hit.block).despawn_recursive();
+            spawn_debris(&mut commands, &item_atlases, x, y);
+            continue;
+        }
+
+        commands.entity(block_hit.block).insert(Bumped::new(block_hit.by, y));
+
+        if block.breakable() {
+            continue;
+        }
+
+        // a hidden block shows up and stays solid once hit
+        if block.kind == BlockKind::Hidden {
+            commands.entity(block_hit.block).insert((CollisionGroups::default(), Revealed));
+        }
+
+        match block.contents {
+            BlockContents::Empty => {}
+            BlockContents::Coin => {
+                spawn_popped_coin(&mut commands, &item_atlases, x, y);
+                coins_collected.send(CoinCollected { player: block_hit.by });
+            }
+            BlockContents::MultiCoin(coins) => {
+                spawn_popped_coin(&mut commands, &item_atlases, x, y);
+                coins_collected.send(CoinCollected { player: block_hit.by });
+
+                // keeps giving coins until its timer runs out or its last coin is given
+                let running = multi_coin_timer.map_or(true, |timer| !timer.0.finished());
+
+                if coins > 1 && running {
+                    if multi_coin_timer.is_none() {
+                        commands
+                            .entity(block_hit.block)
+                            .insert(MultiCoinTimer(Timer::new(MULTI_COIN_DURATION, TimerMode::Once)));
                     }
+
+                    block.contents = BlockContents::MultiCoin(coins - 1);
+                    continue;
+                }
+            }
+            contents => {
+                if let Some(kind) = contents.item() {
+                    spawn_champi(&mut commands, &item_atlases, kind, x, y);
                 }
             }
         }
+
+        block.contents = BlockContents::Empty;
+        commands.entity(block_hit.block).insert(Opened(0.0));
+    }
+}
+
+pub fn tick_multi_coin_timers(time: Res<Time>, mut query: Query<&mut MultiCoinTimer, Without<Opened>>) {
+    for mut timer in query.iter_mut() {
+        timer.0.tick(time.delta());
+    }
+}
+
+pub fn resolve_hidden_blocks(
+    players: Query<(&Player, &Transform, &Collider, &KinematicCharacterController)>,
+    mut blocks: Query<(&Block, &Transform, &mut CollisionGroups), Without<Revealed>>,
+) {
+    if blocks.is_empty() {
+        return;
+    }
+
+    for (block, transform, mut groups) in blocks.iter_mut() {
+        if block.kind != BlockKind::Hidden {
+            continue;
+        }
+
+        let bottom = transform.translation.y - SPRITE_TILE_HEIGHT;
+
+        let mut wanted = COLLISION_GROUPS_HIDDEN;
+
+        for (player, player_transform, collider, controller) in players.iter() {
+            let half_height = collider.as_cuboid().map_or(0.0, |cuboid| cuboid.half_extents().y);
+            let head = player_transform.translation.y + half_height;
+            let rising = controller.translation.map_or(false, |translation| translation.y > 0.0);
+            let below = (player_transform.translation.x - transform.translation.x).abs() < SPRITE_TILE_WIDTH * 2.0;
+
+            if rising && below && head <= bottom + HIDDEN_HIT_TOLERANCE {
+                wanted.memberships |= one_way_solid_group(player);
+            }
+        }
+
+        if *groups != wanted {
+            *groups = wanted;
+        }
+    }
+}
+
+pub fn bump_blocks(
+    mut commands: Commands,
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut query: Query<(Entity, &mut Bumped, &mut Transform), With<Block>>,
+    mut standing: StandingOnBlock,
+) {
+    for (entity, mut bumped, mut transform) in query.iter_mut() {
+        if bumped.timer.tick(time.delta()).finished() {
+            transform.translation.y = bumped.rest_y;
+            commands.entity(entity).remove::<Bumped>();
+            continue;
+        }
+
+        transform.translation.y = bumped.rest_y + BUMP_HEIGHT * (bumped.timer.percent() * PI).sin();
+
+        let center = transform.translation.truncate();
+        knock_out_standing(&mut commands, &rapier_context, entity, center, &mut bumped, &mut standing);
+    }
+}
+
+// Knocks out the enemies and items in a thin box right above the block centered on center, once per bump
+fn knock_out_standing(
+    commands: &mut Commands,
+    rapier_context: &RapierContext,
+    block: Entity,
+    center: Vec2,
+    bumped: &mut Bumped,
+    standing: &mut StandingOnBlock,
+) {
+    // the block collider is scaled twice like its sprite
+    let top = Vec2::new(center.x, center.y + SPRITE_TILE_HEIGHT + BUMP_SENSOR_HEIGHT / 2.0);
+    let sensor = Collider::cuboid(SPRITE_TILE_WIDTH, BUMP_SENSOR_HEIGHT / 2.0);
+
+    let mut touched = vec![];
+    rapier_context.intersections_with_shape(top, 0.0, &sensor, QueryFilter::new().exclude_collider(block), |other| {
+        touched.push(other);
+        true
+    });
+
+    for other in touched {
+        if bumped.knocked.contains(&other) {
+            continue;
+        }
+
+        if let Ok(enemy_transform) = standing.enemies.get(other) {
+            bumped.knocked.push(other);
+            knock_out(commands, other, enemy_transform.translation.x, center.x);
+
+            if let Ok(mut score) = standing.scores.get_mut(bumped.by) {
+                score.0 += SCORE_ENEMY;
+            }
+        } else if let Ok((item_transform, mut velocity, mut direction)) = standing.champis.get_mut(other) {
+            // items jump away from the middle of the block
+            bumped.knocked.push(other);
+            *direction = if item_transform.translation.x < center.x {
+                Direction::Left
+            } else {
+                Direction::Right
+            };
+            velocity.0.y = BUMP_KNOCK_VELOCITY;
+        }
+    }
+}
+
+fn spawn_debris(commands: &mut Commands, item_atlases: &ItemAtlases, x: f32, y: f32) {
+    for (offset, velocity) in DEBRIS.iter() {
+        commands
+            .spawn(SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(SPRITE_IDX_DEBRIS),
+                texture_atlas: item_atlases.items.clone(),
+                transform: Transform {
+                    scale: Vec3::new(
+                        2.0,
+                        2.0,
+                        1.0,
+                    ),
+                    translation: Vec3::new(x + offset.x, y + offset.y, 1.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(Debris {
+                velocity: *velocity,
+                lifetime: Timer::new(DEBRIS_LIFETIME, TimerMode::Once),
+            })
+            .insert(LevelEntity);
+    }
+}
+
+pub fn move_debris(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Debris, &mut Transform)>,
+) {
+    for (entity, mut debris, mut transform) in query.iter_mut() {
+        if debris.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        debris.velocity.y -= DEBRIS_GRAVITY * time.delta_seconds();
+        transform.translation += (debris.velocity * time.delta_seconds()).extend(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app
+            .add_event::<BlockHit>()
+            .add_event::<CoinCollected>()
+            .insert_resource(RapierContext::default())
+            .insert_resource(ItemAtlases { items: Handle::default(), blocks: Handle::default() })
+            .add_systems(Update, (open_block, resolve_hidden_blocks.after(open_block)));
+        app
+    }
+
+    fn hit(app: &mut App, block: Entity, by: Entity) {
+        app.world.send_event(BlockHit { block, by });
+        app.update();
+    }
+
+    #[test]
+    fn a_hidden_multi_coin_block_stays_revealed_and_solid_between_hits() {
+        let mut app = app();
+        let player = app.world.spawn(PowerUp::Small).id();
+        let block = app.world.spawn((
+            Block { kind: BlockKind::Hidden, contents: BlockContents::MultiCoin(3) },
+            Transform::default(),
+            COLLISION_GROUPS_HIDDEN,
+        )).id();
+
+        hit(&mut app, block, player);
+        // no player is below it anymore, a block still hidden would stop colliding
+        app.update();
+
+        let block_ref = app.world.entity(block);
+        assert!(block_ref.contains::<Revealed>());
+        assert!(!block_ref.contains::<Opened>());
+        assert_eq!(block_ref.get::<CollisionGroups>(), Some(&CollisionGroups::default()));
+        assert!(block_ref.get::<Block>().map(|block| block.contents) == Some(BlockContents::MultiCoin(2)));
+
+        hit(&mut app, block, player);
+        app.update();
+
+        let block_ref = app.world.entity(block);
+        assert_eq!(block_ref.get::<CollisionGroups>(), Some(&CollisionGroups::default()));
+        assert!(block_ref.get::<Block>().map(|block| block.contents) == Some(BlockContents::MultiCoin(1)));
+    }
+
+    #[test]
+    fn a_hidden_block_stays_hidden_until_hit() {
+        let mut app = app();
+        let block = app.world.spawn((
+            Block { kind: BlockKind::Hidden, contents: BlockContents::MultiCoin(3) },
+            Transform::default(),
+            CollisionGroups::default(),
+        )).id();
+
+        app.update();
+
+        let block_ref = app.world.entity(block);
+        assert!(!block_ref.contains::<Revealed>());
+        assert_eq!(block_ref.get::<CollisionGroups>(), Some(&COLLISION_GROUPS_HIDDEN));
     }
 }
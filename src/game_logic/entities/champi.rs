@@ -1,39 +1,43 @@
-use std::ops::Deref;
-use std::time::Duration;
 use bevy::asset::Handle;
 use bevy::prelude::*;
-use bevy::prelude::Visibility::Hidden;
 use bevy::sprite::SpriteSheetBundle;
 use bevy_rapier2d::prelude::*;
-use crate::rendering::sprite_manager::{SPRITE_TILE_HEIGHT, SPRITE_TILE_WIDTH};
+use crate::game_logic::entities::emerging::{Emerged, Emerging};
+use crate::game_logic::entities::mario::Direction;
+use crate::game_logic::world::level::{ItemKind, LevelEntity};
+use crate::game_logic::world::physics::{Gravity, KinematicVelocity, Walker};
+use crate::rendering::animation::Animation;
+use crate::rendering::sprite_manager::{ITEM_CYCLE_DELAY, ItemAtlases, SPRITE_IDX_FIRE_FLOWER_ANIM, SPRITE_IDX_ONE_UP, SPRITE_IDX_STAR_ANIM, SPRITE_TILE_HEIGHT, SPRITE_TILE_WIDTH};
 
+const CHAMPI_VELOCITY_X: f32 = 120.0;
+const SPRITE_IDX_MUSHROOM: usize = 5; // in the block atlas
+
+// An item coming out of a block, spawned when the block is hit
 #[derive(Component)]
 pub struct Champi {
-    pub block_id: i32,
-    pub visible: bool,
-    pub upcoming: bool,
-    pub upcoming_height: f32,
+    pub kind: ItemKind,
 }
 
 #[derive(Bundle)]
 pub struct ChampiFactory {
     champi: Champi,
+    emerging: Emerging,
     sprite_bundle: SpriteSheetBundle,
     body: RigidBody,
     collider: Collider,
-    gravity: GravityScale,
 }
 
 impl ChampiFactory {
-    pub fn new(block_id: i32, texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
-        const CYCLE_DELAY: Duration = Duration::from_millis(500);
-
-        const SPRITE_IDX_ANIM: &[usize] = &[0, 1, 2, 3];
+    pub fn new(kind: ItemKind, texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
         Self {
             sprite_bundle: SpriteSheetBundle {
-                sprite: TextureAtlasSprite::new(5),
+                sprite: TextureAtlasSprite::new(match kind {
+                    ItemKind::Mushroom => SPRITE_IDX_MUSHROOM,
+                    ItemKind::FireFlower => SPRITE_IDX_FIRE_FLOWER_ANIM[0],
+                    ItemKind::Star => SPRITE_IDX_STAR_ANIM[0],
+                    ItemKind::OneUp => SPRITE_IDX_ONE_UP,
+                }),
                 texture_atlas,
-                visibility: Hidden,
                 transform: Transform {
                     scale: Vec3::new(
                         2.0,
@@ -50,39 +54,53 @@ impl ChampiFactory {
                 SPRITE_TILE_WIDTH / 2.0,
                 SPRITE_TILE_HEIGHT / 2.0,
             ),
-            gravity: GravityScale(2.0),
             champi: Champi {
-                block_id,
-                visible: false,
-                upcoming: false,
-                upcoming_height: y + SPRITE_TILE_HEIGHT * 2.0,
+                kind,
             },
+            emerging: Emerging::new(Vec3::new(x, y, 0.0), SPRITE_TILE_HEIGHT * 2.0),
         }
     }
 }
 
-pub fn apply_translation_to_champi(
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut Transform, &mut Champi, &mut Visibility)>,
-) {
-    for (entity, mut transform, mut champi, mut visibility) in query.iter_mut() {
-        if champi.visible {
-            *visibility = Visibility::Visible;
+// Spawns an item inside the block at x, y, it rises out of it on its own
+pub fn spawn_champi(commands: &mut Commands, item_atlases: &ItemAtlases, kind: ItemKind, x: f32, y: f32) {
+    let texture_atlas = match kind {
+        ItemKind::Mushroom => item_atlases.blocks.clone(),
+        _ => item_atlases.items.clone(),
+    };
+
+    let mut champi = commands.spawn(ChampiFactory::new(kind, texture_atlas, x, y));
+    champi.insert(LevelEntity);
 
-            if champi.upcoming {
-                transform.translation.y += 0.5;
+    match kind {
+        ItemKind::FireFlower => {
+            champi.insert(Animation::new(SPRITE_IDX_FIRE_FLOWER_ANIM, ITEM_CYCLE_DELAY));
+        }
+        ItemKind::Star => {
+            champi.insert(Animation::new(SPRITE_IDX_STAR_ANIM, ITEM_CYCLE_DELAY));
+        }
+        _ => {}
+    }
+}
 
-                if transform.translation.y >= champi.upcoming_height {
-                    champi.upcoming = false;
-                    commands.entity(entity).remove::<RigidBody>();
-                    commands.entity(entity).insert(RigidBody::Dynamic);
-                }
-            } else {
-                transform.translation.x += 1.0;
-                transform.rotation.w = 0.0
-            }
-        } else {
-            *visibility = Visibility::Hidden;
+// Once out of its block, an item walks and falls like the player does, a flower stays put
+pub fn release_emerged_champi(
+    mut commands: Commands,
+    query: Query<(Entity, &Champi), Added<Emerged>>,
+) {
+    for (entity, champi) in query.iter() {
+        if champi.kind == ItemKind::FireFlower {
+            continue;
         }
+
+        commands
+            .entity(entity)
+            .insert(RigidBody::KinematicPositionBased)
+            .insert(LockedAxes::ROTATION_LOCKED)
+            .insert(KinematicCharacterController::default())
+            .insert(KinematicVelocity(Vec2::new(CHAMPI_VELOCITY_X, 0.0)))
+            .insert(Walker(CHAMPI_VELOCITY_X))
+            .insert(Direction::Right)
+            .insert(Gravity);
     }
 }
@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::entities::mario::{Dead, Player};
+use crate::game_logic::world::level::{CurrentLevel, Level, LevelLoaded};
+use crate::utils::build_point;
+
+const COLOR_CHECKPOINT: Color = Color::rgb(0.85, 0.85, 0.85);
+const COLOR_CHECKPOINT_REACHED: Color = Color::rgb(0.91, 0.35, 0.24);
+const CHECKPOINT_WIDTH: f32 = 6.0;
+const CHECKPOINT_HEIGHT: f32 = 96.0;
+
+// Where the players come back after dying, in world coordinates
+#[derive(Resource, Default)]
+pub struct RespawnPoint(pub Vec2);
+
+#[derive(Component)]
+pub struct Checkpoint;
+
+#[derive(Bundle)]
+pub struct CheckpointFactory {
+    checkpoint: Checkpoint,
+    sprite_bundle: SpriteBundle,
+    body: RigidBody,
+    collider: Collider,
+    sensor: Sensor,
+    active_collision_types: ActiveCollisionTypes,
+}
+
+impl CheckpointFactory {
+    // x and y are the coordinates of the bottom of the checkpoint
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {
+            checkpoint: Checkpoint,
+            sprite_bundle: SpriteBundle {
+                sprite: Sprite {
+                    color: COLOR_CHECKPOINT,
+                    custom_size: Option::from(Vec2::new(CHECKPOINT_WIDTH, CHECKPOINT_HEIGHT)),
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: Vec3::new(x, y + CHECKPOINT_HEIGHT / 2.0, 0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            body: RigidBody::Fixed,
+            collider: Collider::cuboid(CHECKPOINT_WIDTH / 2.0, CHECKPOINT_HEIGHT / 2.0),
+            sensor: Sensor,
+            active_collision_types: ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        }
+    }
+}
+
+pub fn reset_respawn_point(
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut respawn_point: ResMut<RespawnPoint>,
+) {
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+
+    respawn_point.0 = build_point(level.spawn.0, level.spawn.1);
+}
+
+pub fn reach_checkpoint(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    players: Query<Entity, (With<Player>, Without<Dead>)>,
+    mut checkpoints: Query<(Entity, &Transform, &mut Sprite), With<Checkpoint>>,
+    mut respawn_point: ResMut<RespawnPoint>,
+) {
+    if checkpoints.is_empty() {
+        return;
+    }
+
+    for (checkpoint, transform, mut sprite) in checkpoints.iter_mut() {
+        for player in players.iter() {
+            if rapier_context.intersection_pair(player, checkpoint) == Some(true) {
+                respawn_point.0 = transform.translation.truncate();
+                sprite.color = COLOR_CHECKPOINT_REACHED;
+                commands.entity(checkpoint).remove::<Checkpoint>();
+            }
+        }
+    }
+}
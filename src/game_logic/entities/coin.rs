@@ -0,0 +1,169 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::game_logic::entities::emerging::{Emerged, Emerging};
+use crate::game_logic::entities::mario::{Dead, Player};
+use crate::game_logic::lives::Lives;
+use crate::game_logic::world::level::LevelEntity;
+use crate::rendering::animation::Animation;
+use crate::rendering::sprite_manager::{ItemAtlases, SPRITE_IDX_COIN_ANIM, SPRITE_TILE_HEIGHT};
+
+const COIN_POP_VELOCITY: f32 = 700.0;
+const COIN_POP_GRAVITY: f32 = 2400.0;
+const COIN_POP_DURATION: Duration = Duration::from_millis(550);
+const COIN_EMERGE_DURATION: Duration = Duration::from_millis(100);
+const CYCLE_DELAY: Duration = Duration::from_millis(60);
+const PLACED_CYCLE_DELAY: Duration = Duration::from_millis(150);
+const COINS_PER_LIFE: u32 = 100;
+// How close the middle of a player has to get to a coin placed in the level to collect it
+const COLLECT_DISTANCE: Vec2 = Vec2::new(24.0, 36.0);
+
+// Coins collected by every player, each hundredth one gives an extra life to whoever collected it
+#[derive(Resource, Default)]
+pub struct Coins(pub u32);
+
+#[derive(Event)]
+pub struct CoinCollected {
+    pub player: Entity,
+}
+
+// A coin placed in the level
+#[derive(Component)]
+pub struct Coin;
+
+#[derive(Bundle)]
+pub struct CoinFactory {
+    coin: Coin,
+    sprite_bundle: SpriteSheetBundle,
+    animation: Animation,
+}
+
+impl CoinFactory {
+    pub fn new(texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
+        Self {
+            coin: Coin,
+            sprite_bundle: SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(SPRITE_IDX_COIN_ANIM[0]),
+                texture_atlas,
+                transform: Transform {
+                    scale: Vec3::new(
+                        2.0,
+                        2.0,
+                        1.0,
+                    ),
+                    translation: Vec3::new(x, y, 0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            animation: Animation::new(SPRITE_IDX_COIN_ANIM, PLACED_CYCLE_DELAY),
+        }
+    }
+}
+
+// A coin thrown out of a block, only shown for a moment. It quickly emerges from the block, then
+// flies up and falls back before vanishing.
+#[derive(Component)]
+pub struct PoppedCoin {
+    velocity: f32,
+    timer: Timer,
+}
+
+#[derive(Bundle)]
+pub struct PoppedCoinFactory {
+    coin: PoppedCoin,
+    emerging: Emerging,
+    sprite_bundle: SpriteSheetBundle,
+    animation: Animation,
+}
+
+impl PoppedCoinFactory {
+    pub fn new(texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
+        Self {
+            coin: PoppedCoin {
+                velocity: COIN_POP_VELOCITY,
+                timer: Timer::new(COIN_POP_DURATION, TimerMode::Once),
+            },
+            emerging: Emerging::new(Vec3::new(x, y, 0.0), SPRITE_TILE_HEIGHT * 2.0)
+                .with_duration(COIN_EMERGE_DURATION),
+            sprite_bundle: SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(SPRITE_IDX_COIN_ANIM[0]),
+                texture_atlas,
+                transform: Transform {
+                    scale: Vec3::new(
+                        2.0,
+                        2.0,
+                        1.0,
+                    ),
+                    translation: Vec3::new(x, y, 0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            animation: Animation::new(SPRITE_IDX_COIN_ANIM, CYCLE_DELAY),
+        }
+    }
+}
+
+// Spawns a coin inside the block at x, y
+pub fn spawn_popped_coin(commands: &mut Commands, item_atlases: &ItemAtlases, x: f32, y: f32) {
+    commands
+        .spawn(PoppedCoinFactory::new(item_atlases.items.clone(), x, y))
+        .insert(LevelEntity);
+}
+
+pub fn pop_coins(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut PoppedCoin, &mut Transform), With<Emerged>>,
+) {
+    for (entity, mut coin, mut transform) in query.iter_mut() {
+        if coin.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        transform.translation.y += coin.velocity * time.delta_seconds();
+        coin.velocity -= COIN_POP_GRAVITY * time.delta_seconds();
+    }
+}
+
+pub fn collect_coins(
+    mut commands: Commands,
+    players: Query<(Entity, &Transform), (With<Player>, Without<Dead>)>,
+    coins: Query<(Entity, &Transform), With<Coin>>,
+    mut coins_collected: EventWriter<CoinCollected>,
+) {
+    if coins.is_empty() {
+        return;
+    }
+
+    for (coin, coin_transform) in coins.iter() {
+        let collector = players.iter().find(|(_, transform)| {
+            let distance = (transform.translation - coin_transform.translation).truncate().abs();
+            distance.x < COLLECT_DISTANCE.x && distance.y < COLLECT_DISTANCE.y
+        });
+
+        if let Some((player, _)) = collector {
+            commands.entity(coin).despawn_recursive();
+            coins_collected.send(CoinCollected { player });
+        }
+    }
+}
+
+pub fn count_coins(
+    mut coins: ResMut<Coins>,
+    mut coins_collected: EventReader<CoinCollected>,
+    mut players: Query<&mut Lives>,
+) {
+    for coin_collected in coins_collected.iter() {
+        coins.0 += 1;
+
+        if coins.0 >= COINS_PER_LIFE {
+            coins.0 -= COINS_PER_LIFE;
+
+            if let Ok(mut lives) = players.get_mut(coin_collected.player) {
+                lives.0 += 1;
+            }
+        }
+    }
+}
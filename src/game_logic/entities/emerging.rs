@@ -0,0 +1,56 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::game_logic::entities::block::BLOCK_Z;
+
+const EMERGE_DURATION: Duration = Duration::from_millis(800);
+// Between the background and the blocks, so that a rising item looks like it comes out of its block
+const EMERGING_Z: f32 = BLOCK_Z - 0.1;
+
+// Anything rising out of a block, from inside it to right above it. Once out, the entity gets Emerged
+// and its own behaviour takes over.
+#[derive(Component)]
+pub struct Emerging {
+    from_y: f32,
+    height: f32,
+    z: f32,
+    timer: Timer,
+}
+
+impl Emerging {
+    pub fn new(from: Vec3, height: f32) -> Self {
+        Self {
+            from_y: from.y,
+            height,
+            z: from.z,
+            timer: Timer::new(EMERGE_DURATION, TimerMode::Once),
+        }
+    }
+
+    // For items that come out faster than the usual rise, like a coin
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.timer = Timer::new(duration, TimerMode::Once);
+        self
+    }
+}
+
+#[derive(Component)]
+pub struct Emerged;
+
+pub fn emerge(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Emerging, &mut Transform)>,
+) {
+    for (entity, mut emerging, mut transform) in query.iter_mut() {
+        if emerging.timer.tick(time.delta()).finished() {
+            transform.translation.y = emerging.from_y + emerging.height;
+            transform.translation.z = emerging.z;
+
+            commands.entity(entity).remove::<Emerging>().insert(Emerged);
+            continue;
+        }
+
+        transform.translation.y = emerging.from_y + emerging.height * emerging.timer.percent();
+        transform.translation.z = EMERGING_Z;
+    }
+}
@@ -0,0 +1,457 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::entities::fireball::GROUP_FIREBALL;
+use crate::game_logic::entities::mario::{Dead, Direction, Player, PlayerDamaged, Score, Starman};
+use crate::game_logic::world::physics::{Gravity, KinematicVelocity, Walker};
+use crate::rendering::animation::Animation;
+use crate::rendering::sprite_manager::{SPRITE_IDX_GOOMBA_WALK_ANIM, SPRITE_IDX_KOOPA_WALK_ANIM};
+
+pub const GROUP_ENEMY: Group = Group::GROUP_7;
+
+// Enemies walk through each other and through fireballs, move_fireballs is the one looking for them
+const COLLISION_GROUPS_ENEMY_CONTROLLER: CollisionGroups = CollisionGroups::new(
+    GROUP_ENEMY,
+    Group::ALL.difference(GROUP_ENEMY.union(GROUP_FIREBALL)),
+);
+// Enemies touching each other are found with a shape query instead, see collide_enemies
+const COLLISION_GROUPS_ENEMY_QUERY: CollisionGroups = CollisionGroups::new(GROUP_ENEMY, GROUP_ENEMY);
+
+const GOOMBA_VELOCITY_X: f32 = 90.0;
+const GOOMBA_SIZE: f32 = 14.0;
+const KOOPA_VELOCITY_X: f32 = 70.0;
+const KOOPA_WIDTH: f32 = 14.0;
+const KOOPA_HEIGHT: f32 = 24.0;
+const SHELL_VELOCITY_X: f32 = 450.0;
+const SHELL_SIZE: f32 = 14.0;
+// The player who kicked a shell can't be hurt by it until it has moved out of the way
+const KICK_GRACE_DURATION: Duration = Duration::from_millis(250);
+// Points for each enemy defeated in a row by the same kick, the last one repeats
+const SHELL_COMBO_SCORES: &[u32] = &[500, 800, 1000, 2000, 4000, 5000, 8000];
+const CYCLE_DELAY: Duration = Duration::from_millis(200);
+const SQUASHED_DURATION: Duration = Duration::from_millis(500);
+const STOMP_NORMAL_Y: f32 = 0.7; // above this, a contact normal faces up enough for the player to be landing
+const STOMP_BOUNCE_VELOCITY: f32 = 700.0;
+const SCORE_STOMP: u32 = 100;
+const SCORE_STAR: u32 = 200;
+const KNOCKED_OUT_VELOCITY: Vec2 = Vec2::new(120.0, 600.0);
+const KNOCKED_OUT_GRAVITY: f32 = 2400.0;
+const KNOCKED_OUT_DURATION: Duration = Duration::from_millis(1500);
+
+// Anything that hurts the player and can be defeated by fireballs
+#[derive(Component)]
+pub struct Enemy;
+
+#[derive(Component)]
+pub struct Goomba;
+
+// Goes into its shell when stomped
+#[derive(Component)]
+pub struct Koopa;
+
+// A stomped Koopa, it walks like any other enemy once kicked, only much faster
+#[derive(Component, Default)]
+pub struct Shell {
+    // whoever kicked the shell last scores the enemies it defeats
+    kicked_by: Option<Entity>,
+    combo: usize,
+    kick_timer: Timer,
+}
+
+impl Shell {
+    fn protects(&self, player: Entity) -> bool {
+        self.kicked_by == Some(player) && !self.kick_timer.finished()
+    }
+
+    // Each enemy defeated in a row by the same kick is worth more to whoever kicked the shell
+    fn reward_kicker(&mut self, scores: &mut Query<&mut Score>) {
+        let points = SHELL_COMBO_SCORES[self.combo.min(SHELL_COMBO_SCORES.len() - 1)];
+        self.combo += 1;
+
+        if let Some(player) = self.kicked_by {
+            if let Ok(mut score) = scores.get_mut(player) {
+                score.0 += points;
+            }
+        }
+    }
+}
+
+// A stomped enemy, flat for a moment before it disappears
+#[derive(Component)]
+pub struct Squashed(Timer);
+
+// An enemy defeated from below, upside down and falling out of the level without colliding with anything
+#[derive(Component)]
+pub struct KnockedOut {
+    velocity: Vec2,
+    lifetime: Timer,
+}
+
+// from_x is where the hit came from, the enemy is thrown away from it
+pub fn knock_out(commands: &mut Commands, enemy: Entity, enemy_x: f32, from_x: f32) {
+    let direction = if enemy_x < from_x { -1.0 } else { 1.0 };
+
+    commands
+        .entity(enemy)
+        .remove::<(Enemy, Collider, KinematicCharacterController, Walker, Gravity, Animation)>()
+        .insert(KnockedOut {
+            velocity: Vec2::new(KNOCKED_OUT_VELOCITY.x * direction, KNOCKED_OUT_VELOCITY.y),
+            lifetime: Timer::new(KNOCKED_OUT_DURATION, TimerMode::Once),
+        });
+}
+
+#[derive(Bundle)]
+pub struct GoombaFactory {
+    enemy: Enemy,
+    goomba: Goomba,
+    sprite_bundle: SpriteSheetBundle,
+    body: RigidBody,
+    collider: Collider,
+    collision_groups: CollisionGroups,
+    locked_axes: LockedAxes,
+    controller: KinematicCharacterController,
+    velocity: KinematicVelocity,
+    walker: Walker,
+    direction: Direction,
+    gravity: Gravity,
+    animation: Animation,
+}
+
+impl GoombaFactory {
+    pub fn new(texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
+        Self {
+            enemy: Enemy,
+            goomba: Goomba,
+            sprite_bundle: SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(SPRITE_IDX_GOOMBA_WALK_ANIM[0]),
+                texture_atlas,
+                transform: Transform {
+                    scale: Vec3::new(
+                        2.0,
+                        2.0,
+                        1.0,
+                    ),
+                    translation: Vec3::new(x, y, 0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            body: RigidBody::KinematicPositionBased,
+            collider: Collider::cuboid(GOOMBA_SIZE / 2.0, GOOMBA_SIZE / 2.0),
+            collision_groups: CollisionGroups::new(GROUP_ENEMY, Group::ALL),
+            locked_axes: LockedAxes::ROTATION_LOCKED,
+            controller: KinematicCharacterController {
+                filter_groups: Option::from(COLLISION_GROUPS_ENEMY_CONTROLLER),
+                ..Default::default()
+            },
+            velocity: KinematicVelocity::default(),
+            walker: Walker(GOOMBA_VELOCITY_X),
+            direction: Direction::Left,
+            gravity: Gravity,
+            animation: Animation::new(SPRITE_IDX_GOOMBA_WALK_ANIM, CYCLE_DELAY),
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct KoopaFactory {
+    enemy: Enemy,
+    koopa: Koopa,
+    sprite_bundle: SpriteSheetBundle,
+    body: RigidBody,
+    collider: Collider,
+    collision_groups: CollisionGroups,
+    locked_axes: LockedAxes,
+    controller: KinematicCharacterController,
+    velocity: KinematicVelocity,
+    walker: Walker,
+    direction: Direction,
+    gravity: Gravity,
+    animation: Animation,
+}
+
+impl KoopaFactory {
+    pub fn new(texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
+        Self {
+            enemy: Enemy,
+            koopa: Koopa,
+            sprite_bundle: SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(SPRITE_IDX_KOOPA_WALK_ANIM[0]),
+                texture_atlas,
+                transform: Transform {
+                    scale: Vec3::new(
+                        2.0,
+                        2.0,
+                        1.0,
+                    ),
+                    translation: Vec3::new(x, y, 0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            body: RigidBody::KinematicPositionBased,
+            collider: Collider::cuboid(KOOPA_WIDTH / 2.0, KOOPA_HEIGHT / 2.0),
+            collision_groups: CollisionGroups::new(GROUP_ENEMY, Group::ALL),
+            locked_axes: LockedAxes::ROTATION_LOCKED,
+            controller: KinematicCharacterController {
+                filter_groups: Option::from(COLLISION_GROUPS_ENEMY_CONTROLLER),
+                ..Default::default()
+            },
+            velocity: KinematicVelocity::default(),
+            walker: Walker(KOOPA_VELOCITY_X),
+            direction: Direction::Left,
+            gravity: Gravity,
+            animation: Animation::new(SPRITE_IDX_KOOPA_WALK_ANIM, CYCLE_DELAY),
+        }
+    }
+}
+
+// Landing on an enemy squashes it, or puts a Koopa in its shell, and bounces the player up. Any other
+// contact hurts the player, unless they have a star, which knocks the enemy out instead. Shells have
+// their own rules, see touch_shells.
+pub fn touch_enemies(
+    mut commands: Commands,
+    mut players: Query<(Entity, &Transform, &KinematicCharacterControllerOutput, &mut KinematicVelocity, &mut Score, Has<Starman>), (With<Player>, Without<Dead>, Without<Enemy>)>,
+    mut enemies: Query<(Entity, Option<&KinematicCharacterControllerOutput>, &mut Transform, Has<Koopa>), (With<Enemy>, Without<Shell>)>,
+    mut player_damaged: EventWriter<PlayerDamaged>,
+) {
+    if enemies.is_empty() {
+        return;
+    }
+
+    let mut stomped = vec![];
+    // enemies touched by a player with a star, along with that player
+    let mut knocked = vec![];
+
+    for (player, _, output, mut velocity, mut score, starman) in players.iter_mut() {
+        for collision in output.collisions.iter() {
+            if !enemies.contains(collision.entity)
+                || stomped.contains(&collision.entity)
+                || knocked.iter().any(|(enemy, _)| *enemy == collision.entity) {
+                continue;
+            }
+
+            if starman {
+                knocked.push((collision.entity, player));
+            } else if collision.toi.normal1.y > STOMP_NORMAL_Y && output.desired_translation.y <= 0.0 {
+                stomped.push(collision.entity);
+                velocity.0.y = STOMP_BOUNCE_VELOCITY;
+                score.0 += SCORE_STOMP;
+            } else {
+                player_damaged.send(PlayerDamaged { player });
+            }
+        }
+    }
+
+    // an enemy walking into a player standing still
+    for (enemy, output, _, _) in enemies.iter() {
+        let Some(output) = output else { continue };
+        if stomped.contains(&enemy) || knocked.iter().any(|(knocked, _)| *knocked == enemy) {
+            continue;
+        }
+
+        for collision in output.collisions.iter() {
+            let Ok((player, _, _, _, _, starman)) = players.get(collision.entity) else { continue };
+
+            if starman {
+                knocked.push((enemy, player));
+                break;
+            }
+
+            player_damaged.send(PlayerDamaged { player });
+        }
+    }
+
+    for (enemy, player) in knocked {
+        let Ok((_, player_transform, _, _, mut score, _)) = players.get_mut(player) else { continue };
+        let Ok((_, _, transform, _)) = enemies.get(enemy) else { continue };
+
+        knock_out(&mut commands, enemy, transform.translation.x, player_transform.translation.x);
+        score.0 += SCORE_STAR;
+    }
+
+    for enemy in stomped {
+        let Ok((_, _, mut transform, koopa)) = enemies.get_mut(enemy) else { continue };
+
+        if koopa {
+            // the shell keeps the bottom of the Koopa, both are scaled twice like their sprite
+            transform.translation.y -= KOOPA_HEIGHT - SHELL_SIZE;
+
+            commands
+                .entity(enemy)
+                .remove::<(Walker, Animation)>()
+                .insert(Collider::cuboid(SHELL_SIZE / 2.0, SHELL_SIZE / 2.0))
+                .insert(KinematicVelocity::default())
+                .insert(Shell::default());
+        } else {
+            commands
+                .entity(enemy)
+                .remove::<(Enemy, Collider, KinematicCharacterController, Walker, Gravity, Animation)>()
+                .insert(Squashed(Timer::new(SQUASHED_DURATION, TimerMode::Once)));
+        }
+    }
+}
+
+// A shell standing still is kicked away from whoever touches it. Landing on a moving shell stops it,
+// any other contact with it hurts the player. A player with a star knocks any shell out.
+pub fn touch_shells(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut players: Query<(Entity, &Transform, &KinematicCharacterControllerOutput, &mut KinematicVelocity, &mut Score, Has<Starman>), (With<Player>, Without<Dead>)>,
+    mut shells: Query<(Entity, &mut Shell, &Transform, Has<Walker>, Option<&KinematicCharacterControllerOutput>), Without<Player>>,
+    mut player_damaged: EventWriter<PlayerDamaged>,
+) {
+    if shells.is_empty() {
+        return;
+    }
+
+    for (_, mut shell, _, _, _) in shells.iter_mut() {
+        shell.kick_timer.tick(time.delta());
+    }
+
+    let mut touched = vec![];
+
+    for (player, player_transform, output, mut velocity, mut score, starman) in players.iter_mut() {
+        for collision in output.collisions.iter() {
+            if touched.contains(&collision.entity) {
+                continue;
+            }
+            let Ok((entity, mut shell, transform, moving, _)) = shells.get_mut(collision.entity) else { continue };
+
+            touched.push(entity);
+
+            if starman {
+                knock_out(&mut commands, entity, transform.translation.x, player_transform.translation.x);
+                score.0 += SCORE_STAR;
+            } else if !moving {
+                let direction = if player_transform.translation.x < transform.translation.x {
+                    Direction::Right
+                } else {
+                    Direction::Left
+                };
+
+                shell.kicked_by = Some(player);
+                shell.combo = 0;
+                shell.kick_timer = Timer::new(KICK_GRACE_DURATION, TimerMode::Once);
+                commands.entity(entity).insert(Walker(SHELL_VELOCITY_X)).insert(direction);
+            } else if collision.toi.normal1.y > STOMP_NORMAL_Y && output.desired_translation.y <= 0.0 {
+                velocity.0.y = STOMP_BOUNCE_VELOCITY;
+                commands.entity(entity).remove::<Walker>().insert(KinematicVelocity::default());
+            } else if !shell.protects(player) {
+                player_damaged.send(PlayerDamaged { player });
+            }
+        }
+    }
+
+    // a moving shell coming back at a player
+    for (entity, shell, transform, moving, output) in shells.iter() {
+        let Some(output) = output else { continue };
+        if !moving || touched.contains(&entity) {
+            continue;
+        }
+
+        for collision in output.collisions.iter() {
+            let Ok((player, player_transform, _, _, mut score, starman)) = players.get_mut(collision.entity) else { continue };
+
+            if starman {
+                knock_out(&mut commands, entity, transform.translation.x, player_transform.translation.x);
+                score.0 += SCORE_STAR;
+                break;
+            } else if !shell.protects(player) {
+                player_damaged.send(PlayerDamaged { player });
+            }
+        }
+    }
+}
+
+// Enemies walking into each other turn around, unless one of them is a moving shell, which knocks the
+// other out. Two moving shells knock each other out.
+pub fn collide_enemies(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut enemies: Query<(Entity, &Transform, &Collider, &mut Direction, Has<Walker>, Option<&mut Shell>), With<Enemy>>,
+    mut scores: Query<&mut Score>,
+) {
+    if enemies.is_empty() {
+        return;
+    }
+
+    let mut contacts = vec![];
+
+    for (entity, transform, collider, _, _, _) in enemies.iter() {
+        let filter = QueryFilter::new().groups(COLLISION_GROUPS_ENEMY_QUERY).exclude_collider(entity);
+
+        rapier_context.intersections_with_shape(transform.translation.truncate(), 0.0, collider, filter, |other| {
+            contacts.push((entity, other));
+            true
+        });
+    }
+
+    let mut defeated = vec![];
+
+    // every contact is found from both sides, each enemy only reacts for itself
+    for (entity, other) in contacts {
+        if defeated.contains(&entity) || defeated.contains(&other) {
+            continue;
+        }
+        let Ok((_, other_transform, _, _, other_moving, other_shell)) = enemies.get(other) else { continue };
+        let other_x = other_transform.translation.x;
+        let other_is_moving_shell = other_moving && other_shell.is_some();
+
+        let Ok((_, transform, _, mut direction, moving, shell)) = enemies.get_mut(entity) else { continue };
+        let x = transform.translation.x;
+
+        match shell {
+            Some(mut shell) if moving => {
+                defeated.push(other);
+                knock_out(&mut commands, other, other_x, x);
+                shell.reward_kicker(&mut scores);
+
+                if other_is_moving_shell {
+                    defeated.push(entity);
+                    knock_out(&mut commands, entity, x, other_x);
+
+                    if let Ok((_, _, _, _, _, Some(mut other_shell))) = enemies.get_mut(other) {
+                        other_shell.reward_kicker(&mut scores);
+                    }
+                }
+            }
+            _ if moving && !other_is_moving_shell => {
+                *direction = if x < other_x {
+                    Direction::Left
+                } else {
+                    Direction::Right
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn clear_squashed(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Squashed)>,
+) {
+    for (entity, mut squashed) in query.iter_mut() {
+        if squashed.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn fall_knocked_out(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut KnockedOut, &mut Transform)>,
+) {
+    for (entity, mut knocked_out, mut transform) in query.iter_mut() {
+        if knocked_out.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        knocked_out.velocity.y -= KNOCKED_OUT_GRAVITY * time.delta_seconds();
+        transform.translation += (knocked_out.velocity * time.delta_seconds()).extend(0.0);
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::entities::enemy::Enemy;
+use crate::game_logic::entities::mario::{Dead, Direction, Player, PowerUp, Score};
+use crate::game_logic::input::{ActionState, PlayerAction};
+use crate::game_logic::world::level::LevelEntity;
+use crate::game_logic::world::physics::{Gravity, KinematicVelocity};
+use crate::rendering::animation::Animation;
+use crate::rendering::sprite_manager::{ItemAtlases, SPRITE_IDX_FIREBALL_ANIM};
+
+pub const GROUP_FIREBALL: Group = Group::GROUP_6;
+
+// Fireballs go through the player who threw them and through each other
+const COLLISION_GROUPS_FIREBALL: CollisionGroups = CollisionGroups::new(
+    GROUP_FIREBALL,
+    Group::ALL.difference(Group::GROUP_2.union(GROUP_FIREBALL)),
+);
+
+const MAX_FIREBALLS: usize = 2; // per player on screen
+const FIREBALL_VELOCITY_X: f32 = 700.0;
+const FIREBALL_BOUNCE_VELOCITY: f32 = 550.0;
+const FIREBALL_RADIUS: f32 = 4.0;
+const FIREBALL_LIFETIME: Duration = Duration::from_millis(3000);
+const CYCLE_DELAY: Duration = Duration::from_millis(50);
+const SCORE_ENEMY: u32 = 200;
+
+#[derive(Component)]
+pub struct Fireball {
+    owner: Entity,
+    lifetime: Timer,
+}
+
+#[derive(Bundle)]
+pub struct FireballFactory {
+    fireball: Fireball,
+    sprite_bundle: SpriteSheetBundle,
+    body: RigidBody,
+    collider: Collider,
+    collision_groups: CollisionGroups,
+    controller: KinematicCharacterController,
+    velocity: KinematicVelocity,
+    gravity: Gravity,
+    animation: Animation,
+}
+
+impl FireballFactory {
+    pub fn new(owner: Entity, texture_atlas: Handle<TextureAtlas>, x: f32, y: f32, direction: &Direction) -> Self {
+        let velocity_x = match direction {
+            Direction::Right => FIREBALL_VELOCITY_X,
+            Direction::Left => -FIREBALL_VELOCITY_X,
+        };
+
+        Self {
+            fireball: Fireball {
+                owner,
+                lifetime: Timer::new(FIREBALL_LIFETIME, TimerMode::Once),
+            },
+            sprite_bundle: SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(SPRITE_IDX_FIREBALL_ANIM[0]),
+                texture_atlas,
+                transform: Transform {
+                    scale: Vec3::new(
+                        2.0,
+                        2.0,
+                        1.0,
+                    ),
+                    translation: Vec3::new(x, y, 0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            body: RigidBody::KinematicPositionBased,
+            collider: Collider::ball(FIREBALL_RADIUS),
+            collision_groups: COLLISION_GROUPS_FIREBALL,
+            controller: KinematicCharacterController {
+                filter_groups: Option::from(COLLISION_GROUPS_FIREBALL),
+                snap_to_ground: None,
+                ..Default::default()
+            },
+            velocity: KinematicVelocity(Vec2::new(velocity_x, -FIREBALL_BOUNCE_VELOCITY)),
+            gravity: Gravity,
+            animation: Animation::new(SPRITE_IDX_FIREBALL_ANIM, CYCLE_DELAY),
+        }
+    }
+}
+
+pub fn throw_fireball(
+    mut commands: Commands,
+    item_atlases: Res<ItemAtlases>,
+    players: Query<(Entity, &Transform, &Direction, &PowerUp, &ActionState), (With<Player>, Without<Dead>)>,
+    fireballs: Query<&Fireball>,
+) {
+    for (player, transform, direction, power_up, actions) in players.iter() {
+        if *power_up != PowerUp::Fire || !actions.just_pressed(PlayerAction::Run) {
+            continue;
+        }
+
+        if fireballs.iter().filter(|fireball| fireball.owner == player).count() >= MAX_FIREBALLS {
+            continue;
+        }
+
+        let offset = match direction {
+            Direction::Right => 16.0,
+            Direction::Left => -16.0,
+        };
+
+        commands
+            .spawn(FireballFactory::new(
+                player,
+                item_atlases.items.clone(),
+                transform.translation.x + offset,
+                transform.translation.y,
+                direction,
+            ))
+            .insert(LevelEntity);
+    }
+}
+
+// Fireballs bounce on the ground and disappear against walls, when hitting an enemy or after a while
+pub fn move_fireballs(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut fireballs: Query<(Entity, &mut Fireball, &mut KinematicVelocity, Option<&KinematicCharacterControllerOutput>)>,
+    enemies: Query<Entity, With<Enemy>>,
+    mut scores: Query<&mut Score>,
+) {
+    for (entity, mut fireball, mut velocity, output) in fireballs.iter_mut() {
+        if fireball.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let Some(output) = output else { continue };
+
+        if let Some(enemy) = output.collisions.iter().find_map(|collision| enemies.get(collision.entity).ok()) {
+            commands.entity(enemy).despawn_recursive();
+            commands.entity(entity).despawn_recursive();
+
+            if let Ok(mut score) = scores.get_mut(fireball.owner) {
+                score.0 += SCORE_ENEMY;
+            }
+            continue;
+        }
+
+        if output.effective_translation.x.abs() < output.desired_translation.x.abs() / 2.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        if output.grounded && velocity.0.y <= 0.0 {
+            velocity.0.y = FIREBALL_BOUNCE_VELOCITY;
+        }
+    }
+}
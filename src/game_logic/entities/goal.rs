@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::entities::mario::Player;
+use crate::game_logic::world::level::LevelCompleted;
+
+const COLOR_GOAL: Color = Color::rgb(0.24, 0.71, 0.29);
+const GOAL_WIDTH: f32 = 8.0;
+const GOAL_HEIGHT: f32 = 320.0;
+
+#[derive(Component)]
+pub struct Goal;
+
+#[derive(Bundle)]
+pub struct GoalFactory {
+    goal: Goal,
+    sprite_bundle: SpriteBundle,
+    body: RigidBody,
+    collider: Collider,
+    sensor: Sensor,
+    active_collision_types: ActiveCollisionTypes,
+}
+
+impl GoalFactory {
+    // x and y are the coordinates of the bottom of the flagpole
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {
+            goal: Goal,
+            sprite_bundle: SpriteBundle {
+                sprite: Sprite {
+                    color: COLOR_GOAL,
+                    custom_size: Option::from(Vec2::new(GOAL_WIDTH, GOAL_HEIGHT)),
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: Vec3::new(x, y + GOAL_HEIGHT / 2.0, 0.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            body: RigidBody::Fixed,
+            collider: Collider::cuboid(GOAL_WIDTH / 2.0, GOAL_HEIGHT / 2.0),
+            sensor: Sensor,
+            // the player is kinematic and the flagpole fixed, rapier ignores this pair by default
+            active_collision_types: ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        }
+    }
+}
+
+pub fn reach_goal(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    players: Query<Entity, With<Player>>,
+    goals: Query<Entity, With<Goal>>,
+    mut level_completed: EventWriter<LevelCompleted>,
+) {
+    if goals.is_empty() {
+        return;
+    }
+
+    for goal in goals.iter() {
+        for player in players.iter() {
+            if rapier_context.intersection_pair(player, goal) == Some(true) {
+                commands.entity(goal).remove::<Goal>();
+                level_completed.send(LevelCompleted);
+            }
+        }
+    }
+}
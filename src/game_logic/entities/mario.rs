@@ -3,12 +3,19 @@ use bevy::ecs::bundle::DynamicBundle;
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use crate::game_logic::entities::champi::Champi;
-use crate::rendering::animation::Animation;
-use crate::rendering::sprite_manager::{SPRITE_OFFSET_X, SPRITE_PADDING_X, SPRITE_PADDING_Y, SPRITESHEET_COLS, SPRITESHEET_ROWS};
-
+use crate::game_logic::entities::checkpoint::RespawnPoint;
+use crate::game_logic::input::{ActionState, PlayerAction};
+use crate::game_logic::lives::Lives;
+use crate::game_logic::world::level::ItemKind;
+use crate::game_logic::world::physics::{approach, Gravity, KinematicVelocity};
+use crate::game_logic::world::platform::{GROUP_ONE_WAY, GROUP_ONE_WAY_SOLID_ANY, one_way_solid_group};
+use crate::game_logic::world::tuning::MovementTuning;
+
+// One-way platforms and hidden blocks are only solid for the players they are made solid for, see
+// Player::collision_groups
 pub const COLLISION_GROUPS_DEFAULT: CollisionGroups = CollisionGroups::new(
     Group::GROUP_2,
-    Group::ALL.difference(Group::GROUP_3),
+    Group::ALL.difference(Group::GROUP_3.union(GROUP_ONE_WAY).union(GROUP_ONE_WAY_SOLID_ANY)),
 );
 
 #[derive(Component)]
@@ -17,17 +24,171 @@ pub enum Direction {
     Left,
 }
 
+// index is the position of the player in the input bindings and on the HUD
+#[derive(Component)]
+pub struct Player {
+    pub index: usize,
+}
+
+impl Player {
+    // Filter of the character controller of this player
+    pub fn collision_groups(&self) -> CollisionGroups {
+        CollisionGroups::new(
+            COLLISION_GROUPS_DEFAULT.memberships,
+            COLLISION_GROUPS_DEFAULT.filters | one_way_solid_group(self),
+        )
+    }
+}
+
+// Both characters play the same, only their palette differs
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum Character {
+    Mario,
+    Luigi,
+}
+
+impl Character {
+    pub fn name(self) -> &'static str {
+        match self {
+            Character::Mario => "MARIO",
+            Character::Luigi => "LUIGI",
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct Score(pub u32);
+
+// What the player is doing, the only thing the sprite and the animation are chosen from
+#[derive(Component, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlayerState {
+    #[default]
+    Idle,
+    Walk,
+    Run,
+    Skid,
+    Jump,
+    Fall,
+    Crouch,
+    Grow,
+    Dead,
+}
+
+// Only big players crouch, they keep crouching until the action is released and there is room to stand up
+#[derive(Component)]
+pub struct Crouching;
+
+// The player keeps the Grow state until the timer ends
+#[derive(Component)]
+pub struct Growing(Timer);
+
+impl Default for Growing {
+    fn default() -> Self {
+        Self(Timer::new(GROW_DURATION, TimerMode::Once))
+    }
+}
+
+// Height climbed since the jump started
 #[derive(Component)]
 pub struct Jump(f32);
 
+// Seconds since the player last stood on the ground and since the jump action was last pressed,
+// a jump starts when both are short enough (coyote time and jump buffering)
 #[derive(Component)]
-pub struct Big(f32);
+pub struct JumpTiming {
+    since_grounded: f32,
+    since_pressed: f32,
+}
+
+impl Default for JumpTiming {
+    fn default() -> Self {
+        Self {
+            since_grounded: f32::INFINITY,
+            since_pressed: f32::INFINITY,
+        }
+    }
+}
+
+// Power-ups from the weakest to the strongest, taking damage drops one tier
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum PowerUp {
+    #[default]
+    Small,
+    Big,
+    Fire,
+}
+
+impl PowerUp {
+    // None when the player has nothing left to lose
+    pub fn downgrade(self) -> Option<PowerUp> {
+        match self {
+            PowerUp::Small => None,
+            PowerUp::Big => Some(PowerUp::Small),
+            PowerUp::Fire => Some(PowerUp::Big),
+        }
+    }
 
-const PLAYER_VELOCITY_X: f32 = 400.0;
-const PLAYER_VELOCITY_Y: f32 = 850.0;
-const MAX_JUMP_HEIGHT: f32 = 230.0;
-const SPRITE_IDX_WALKING: &[usize] = &[0, 4, 3];
-const CYCLE_DELAY: Duration = Duration::from_millis(70);
+    pub fn height(self) -> f32 {
+        match self {
+            PowerUp::Small => SPRITE_MARIO_HEIGHT,
+            PowerUp::Big | PowerUp::Fire => SPRITE_BIG_MARIO_HEIGHT,
+        }
+    }
+
+    pub fn can_crouch(self) -> bool {
+        self != PowerUp::Small
+    }
+}
+
+// The player cannot be damaged again until the timer ends
+#[derive(Component)]
+pub struct Invincible(pub Timer);
+
+impl Default for Invincible {
+    fn default() -> Self {
+        Self(Timer::new(INVINCIBILITY_DURATION, TimerMode::Once))
+    }
+}
+
+// The invincibility given by a star rather than by being hurt, enemies touched are defeated
+#[derive(Component)]
+pub struct Starman;
+
+// Sent by anything that hurts the player, see take_damage
+#[derive(Event)]
+pub struct PlayerDamaged {
+    pub player: Entity,
+}
+
+// The player is frozen on the death frame until the timer ends, then comes back at the respawn point
+#[derive(Component)]
+pub struct Dead(Timer);
+
+impl Default for Dead {
+    fn default() -> Self {
+        Self(Timer::new(DEATH_DURATION, TimerMode::Once))
+    }
+}
+
+impl Dead {
+    pub fn finished(&self) -> bool {
+        self.0.finished()
+    }
+}
+
+// Sent when a rising player hits something with its head, entity is what was hit
+#[derive(Event)]
+pub struct HeadBump {
+    pub player: Entity,
+    pub entity: Entity,
+}
+
+const GROW_DURATION: Duration = Duration::from_millis(600);
+const INVINCIBILITY_DURATION: Duration = Duration::from_millis(2000);
+const STAR_DURATION: Duration = Duration::from_millis(10000);
+const HEAD_BUMP_NORMAL_Y: f32 = -0.7; // below this, a contact normal faces down enough to be a ceiling
+const STAND_UP_MARGIN: f32 = 1.0; // keeps the ground out of the standing up check
+const DEATH_DURATION: Duration = Duration::from_millis(2000);
 
 const SPRITE_MARIO_WIDTH: f32 = 13.0;
 const SPRITE_MARIO_HEIGHT: f32 = 23.0;
@@ -36,6 +197,16 @@ const SPRITE_BIG_MARIO_HEIGHT: f32 = 30.0;
 
 #[derive(Bundle)]
 pub struct Mario {
+    player: Player,
+    character: Character,
+    lives: Lives,
+    score: Score,
+    state: PlayerState,
+    power_up: PowerUp,
+    actions: ActionState,
+    jump_timing: JumpTiming,
+    velocity: KinematicVelocity,
+    gravity: Gravity,
     sprite_bundle: SpriteSheetBundle,
     body: RigidBody,
     collider: Collider,
@@ -43,8 +214,18 @@ pub struct Mario {
 }
 
 impl Mario {
-    pub fn new(texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
+    pub fn new(index: usize, character: Character, texture_atlas: Handle<TextureAtlas>, x: f32, y: f32) -> Self {
         Self {
+            player: Player { index },
+            character,
+            lives: Lives::default(),
+            score: Score::default(),
+            state: PlayerState::default(),
+            power_up: PowerUp::default(),
+            actions: ActionState::default(),
+            jump_timing: JumpTiming::default(),
+            velocity: KinematicVelocity::default(),
+            gravity: Gravity,
             sprite_bundle: SpriteSheetBundle {
                 sprite: TextureAtlasSprite::new(0),
                 texture_atlas,
@@ -71,180 +252,463 @@ impl Mario {
 
 
 pub fn movement(
-    input: Res<Input<KeyCode>>,
     time: Res<Time>,
-    mut query: Query<&mut KinematicCharacterController>,
+    tuning: Res<MovementTuning>,
+    mut query: Query<
+        (Option<&KinematicCharacterControllerOutput>, &mut KinematicVelocity, &ActionState, Has<Crouching>),
+        Without<Dead>,
+    >,
 ) {
-    for mut player in query.iter_mut() {
-        let mut movement = 0.0;
+    for (output, mut velocity, actions, crouching) in query.iter_mut() {
+        let delta = time.delta_seconds();
+        let mut direction = 0.0;
+        let grounded = output.map_or(false, |output| output.grounded);
+
+        // crouching on the ground, the player can only slide with the momentum it had
+        if crouching && grounded {
+            velocity.0.x = approach(velocity.0.x, 0.0, tuning.crouch_deceleration * delta);
+            continue;
+        }
 
-        if input.pressed(KeyCode::Right) {
-            movement += time.delta_seconds() * PLAYER_VELOCITY_X;
+        if actions.pressed(PlayerAction::MoveRight) {
+            direction += 1.0;
+        }
+
+        if actions.pressed(PlayerAction::MoveLeft) {
+            direction -= 1.0;
+        }
+
+        // running into a wall kills the momentum
+        if let Some(output) = output {
+            if output.desired_translation.x != 0.0 && output.effective_translation.x == 0.0 {
+                velocity.0.x = 0.0;
+            }
         }
 
-        if input.pressed(KeyCode::Left) {
-            movement += time.delta_seconds() * PLAYER_VELOCITY_X * -1.0;
+        let top_speed = if actions.pressed(PlayerAction::Run) {
+            tuning.run_speed
+        } else {
+            tuning.walk_speed
+        };
+
+        velocity.0.x = if direction == 0.0 {
+            approach(velocity.0.x, 0.0, tuning.deceleration * delta)
+        } else if velocity.0.x * direction < 0.0 {
+            approach(velocity.0.x, 0.0, tuning.skid_deceleration * delta)
+        } else if velocity.0.x.abs() > top_speed {
+            approach(velocity.0.x, direction * top_speed, tuning.deceleration * delta)
+        } else {
+            approach(velocity.0.x, direction * top_speed, tuning.acceleration * delta)
+        };
+    }
+}
+
+pub fn track_jump_timing(
+    time: Res<Time>,
+    mut query: Query<(&KinematicCharacterControllerOutput, &ActionState, &mut JumpTiming)>,
+) {
+    for (output, actions, mut timing) in query.iter_mut() {
+        if output.grounded {
+            timing.since_grounded = 0.0;
+        } else {
+            timing.since_grounded += time.delta_seconds();
         }
 
-        match player.translation {
-            Some(vec) => player.translation = Some(Vec2::new(movement, vec.y)), // update if it already exists
-            None => player.translation = Some(Vec2::new(movement, 0.0)),
+        if actions.just_pressed(PlayerAction::Jump) {
+            timing.since_pressed = 0.0;
+        } else {
+            timing.since_pressed += time.delta_seconds();
         }
     }
 }
 
 pub fn jump(
-    input: Res<Input<KeyCode>>,
     mut commands: Commands,
-    mut query_character_controller: Query<&mut KinematicCharacterController>,
-    query: Query<
-        (Entity, &KinematicCharacterControllerOutput),
-        (With<KinematicCharacterController>, Without<Jump>),
+    tuning: Res<MovementTuning>,
+    mut query: Query<
+        (Entity, &mut JumpTiming, &mut KinematicVelocity),
+        (With<KinematicCharacterController>, Without<Jump>, Without<Dead>),
     >
 ) {
     if query.is_empty() {
         return;
     }
-    for mut character_controller in query_character_controller.iter_mut() {
-        for (player, output) in query.iter() {
-            if input.pressed(KeyCode::Up) && output.grounded {
-                commands.entity(player).insert(Jump(0.0));
-                character_controller.filter_groups = Option::from(CollisionGroups::new(
-                    Group::GROUP_2,
-                    Group::ALL - Group::GROUP_1 - Group::GROUP_3,
-                ));
+
+    for (player, mut timing, mut velocity) in query.iter_mut() {
+        if timing.since_pressed <= tuning.jump_buffer_time && timing.since_grounded <= tuning.coyote_time {
+            // the faster the player runs beyond walking speed, the higher the jump
+            let run_ratio = ((velocity.0.x.abs() - tuning.walk_speed) / (tuning.run_speed - tuning.walk_speed))
+                .clamp(0.0, 1.0);
+
+            velocity.0.y = tuning.jump_velocity(tuning.jump_height + tuning.run_jump_bonus * run_ratio);
+            commands.entity(player).insert(Jump(0.0));
+
+            // consume both windows so that a single press never triggers two jumps
+            timing.since_pressed = f32::INFINITY;
+            timing.since_grounded = f32::INFINITY;
+        }
+    }
+}
+
+// Hitting a ceiling ends the rise right away, the player starts falling on the next frame
+pub fn detect_head_bump(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &KinematicCharacterControllerOutput, &mut KinematicVelocity),
+        (With<Player>, Without<Dead>),
+    >,
+    mut head_bumps: EventWriter<HeadBump>,
+) {
+    for (player, output, mut velocity) in query.iter_mut() {
+        if output.desired_translation.y <= 0.0 {
+            continue;
+        }
+
+        let mut bumped = false;
+
+        for collision in output.collisions.iter() {
+            if collision.toi.normal1.y < HEAD_BUMP_NORMAL_Y {
+                bumped = true;
+                head_bumps.send(HeadBump {
+                    player,
+                    entity: collision.entity,
+                });
             }
         }
+
+        if bumped {
+            velocity.0.y = velocity.0.y.min(0.0);
+            commands.entity(player).remove::<Jump>();
+        }
     }
 }
 
+// Gravity does the actual rising, the jump only lasts as long as the player goes up and holds the jump action
 pub fn rise(
     mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(Entity, &mut KinematicCharacterController, &mut Jump)>,
+    tuning: Res<MovementTuning>,
+    mut query: Query<(Entity, &mut KinematicVelocity, &mut Jump, &ActionState), Without<Dead>>,
 ) {
     if query.is_empty() {
         return;
     }
 
-    for (entity, mut player, mut jump) in query.iter_mut() {
-        let mut movement = time.delta().as_secs_f32() * PLAYER_VELOCITY_Y;
-
-        if movement + jump.0 >= MAX_JUMP_HEIGHT {
-            movement = MAX_JUMP_HEIGHT - jump.0;
+    for (entity, mut velocity, mut jump, actions) in query.iter_mut() {
+        if velocity.0.y <= 0.0 {
             commands.entity(entity).remove::<Jump>();
-            player.filter_groups = Option::from(COLLISION_GROUPS_DEFAULT);
+            continue;
         }
 
-        jump.0 += movement;
+        jump.0 += velocity.0.y * time.delta_seconds();
 
-        match player.translation {
-            Some(vec) => player.translation = Some(Vec2::new(vec.x, movement)),
-            None => player.translation = Some(Vec2::new(0.0, movement)),
+        // releasing the jump action early cuts the jump short
+        if !actions.pressed(PlayerAction::Jump) && jump.0 >= tuning.min_jump_height {
+            velocity.0.y *= tuning.jump_cut;
+            commands.entity(entity).remove::<Jump>();
+        }
+    }
+}
+
+pub fn update_player_state(
+    mut commands: Commands,
+    time: Res<Time>,
+    tuning: Res<MovementTuning>,
+    mut query: Query<(
+        Entity,
+        &mut PlayerState,
+        &KinematicCharacterControllerOutput,
+        &KinematicVelocity,
+        &ActionState,
+        Option<&mut Growing>,
+        Has<Crouching>,
+        Has<Dead>,
+    )>,
+) {
+    for (player, mut state, output, velocity, actions, growing, crouching, dead) in query.iter_mut() {
+        let growing = match growing {
+            Some(mut growing) if !dead => {
+                if growing.0.tick(time.delta()).finished() {
+                    commands.entity(player).remove::<Growing>();
+                }
+                true
+            }
+            _ => false,
+        };
+
+        let next = if dead {
+            PlayerState::Dead
+        } else if growing {
+            PlayerState::Grow
+        } else if crouching {
+            PlayerState::Crouch
+        } else if !output.grounded {
+            // a jump keeps its pose until landing, walking off a ledge only falls
+            if velocity.0.y > 0.0 || *state == PlayerState::Jump {
+                PlayerState::Jump
+            } else {
+                PlayerState::Fall
+            }
+        } else if is_skidding(velocity, actions) {
+            PlayerState::Skid
+        } else if velocity.0.x.abs() > tuning.walk_speed {
+            PlayerState::Run
+        } else if velocity.0.x != 0.0 {
+            PlayerState::Walk
+        } else {
+            PlayerState::Idle
+        };
+
+        if *state != next {
+            *state = next;
         }
     }
 }
 
-pub fn fall(time: Res<Time>, mut query: Query<&mut KinematicCharacterController, Without<Jump>>) {
+// Pushing against the current direction
+fn is_skidding(velocity: &KinematicVelocity, actions: &ActionState) -> bool {
+    (velocity.0.x > 0.0 && actions.pressed(PlayerAction::MoveLeft) && !actions.pressed(PlayerAction::MoveRight))
+        || (velocity.0.x < 0.0 && actions.pressed(PlayerAction::MoveRight) && !actions.pressed(PlayerAction::MoveLeft))
+}
+
+pub fn update_direction(
+    mut commands: Commands,
+    query: Query<(Entity, &ActionState), (With<Player>, Without<Dead>)>,
+) {
     if query.is_empty() {
         return;
     }
 
-    for mut player in query.iter_mut() {
-        // I am using two-thirds of the Y-velocity since I want the character to fall slower than it rises
-        let movement = time.delta().as_secs_f32() * (PLAYER_VELOCITY_Y / 1.5) * -1.0;
+    for (player, actions) in query.iter() {
+        let right = actions.pressed(PlayerAction::MoveRight);
+        let left = actions.pressed(PlayerAction::MoveLeft);
 
-        match player.translation {
-            Some(vec) => player.translation = Some(Vec2::new(vec.x, movement)),
-            None => player.translation = Some(Vec2::new(0.0, movement)),
+        if right && !left {
+            commands.entity(player).insert(Direction::Right);
+        } else if left && !right {
+            commands.entity(player).insert(Direction::Left);
         }
     }
 }
 
-pub fn apply_movement_animation(
+pub fn detect_collision_with_champi(
     mut commands: Commands,
-    query: Query<(Entity, &KinematicCharacterControllerOutput), Without<Animation>>,
+    mut query: Query<(Entity, &KinematicCharacterControllerOutput, &mut PowerUp, &mut Lives), (With<Player>, Without<Dead>)>,
+    query_champi: Query<(Entity, &Champi, Option<&KinematicCharacterControllerOutput>), Without<Player>>,
 ) {
-    if query.is_empty() {
+    if query.is_empty() || query_champi.is_empty() {
         return;
     }
 
-    for (player, output) in query.iter() {
-        if output.desired_translation.x != 0.0 && output.grounded {
-            commands
-                .entity(player)
-                .insert(Animation::new(SPRITE_IDX_WALKING, CYCLE_DELAY));
+    // a walking item can also run into a player standing still
+    let mut contacts = vec![];
+
+    for (player, output, _, _) in query.iter() {
+        for collision in output.collisions.iter() {
+            if query_champi.contains(collision.entity) {
+                contacts.push((player, collision.entity));
+            }
+        }
+    }
+    for (champi, _, output) in query_champi.iter() {
+        for collision in output.iter().flat_map(|output| output.collisions.iter()) {
+            if query.contains(collision.entity) {
+                contacts.push((collision.entity, champi));
+            }
+        }
+    }
+
+    // an item can show up in several collisions, or be touched by both players, it is only collected once
+    let mut collected = vec![];
+
+    for (player, champi_entity) in contacts {
+        if collected.contains(&champi_entity) {
+            continue;
+        }
+        let Ok((_, _, mut power_up, mut lives)) = query.get_mut(player) else { continue };
+        let Ok((_, champi, _)) = query_champi.get(champi_entity) else { continue };
+
+        collected.push(champi_entity);
+        commands.entity(champi_entity).despawn();
+
+        match champi.kind {
+            ItemKind::Mushroom if *power_up == PowerUp::Small => *power_up = PowerUp::Big,
+            ItemKind::FireFlower => *power_up = PowerUp::Fire,
+            ItemKind::Star => {
+                commands
+                    .entity(player)
+                    .insert(Invincible(Timer::new(STAR_DURATION, TimerMode::Once)))
+                    .insert(Starman);
+            }
+            ItemKind::OneUp => lives.0 += 1,
+            _ => {}
         }
     }
 }
 
-pub fn update_direction(
+pub fn take_damage(
     mut commands: Commands,
-    query: Query<(Entity, &KinematicCharacterControllerOutput)>,
+    mut player_damaged: EventReader<PlayerDamaged>,
+    mut query: Query<(&mut PowerUp, Has<Invincible>), Without<Dead>>,
 ) {
-    if query.is_empty() {
-        return;
+    // several sources can hurt a player in the same frame, only the first one counts
+    let mut damaged = vec![];
+
+    for event in player_damaged.iter() {
+        let Ok((mut power_up, invincible)) = query.get_mut(event.player) else { continue };
+
+        if invincible || damaged.contains(&event.player) {
+            continue;
+        }
+        damaged.push(event.player);
+
+        match power_up.downgrade() {
+            Some(downgraded) => {
+                *power_up = downgraded;
+                commands.entity(event.player).insert(Invincible::default());
+            }
+            None => {
+                commands.entity(event.player).insert(Dead::default());
+            }
+        }
     }
+}
 
-    for (player, output) in query.iter() {
-        if output.desired_translation.x > 0.0 {
-            commands.entity(player).insert(Direction::Right);
-        } else if output.desired_translation.x < 0.0 {
-            commands.entity(player).insert(Direction::Left);
+pub fn tick_invincibility(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invincible)>,
+) {
+    for (player, mut invincible) in query.iter_mut() {
+        if invincible.0.tick(time.delta()).just_finished() {
+            commands.entity(player).remove::<(Invincible, Starman)>();
         }
     }
 }
 
-pub fn detect_collision_with_champi(
+pub fn crouch(
     mut commands: Commands,
-    query: Query<(Entity, &KinematicCharacterControllerOutput)>,
-    mut query_champi: Query<(Entity, &mut Transform, &mut Champi)>,
+    rapier_context: Res<RapierContext>,
+    query: Query<
+        (Entity, &Transform, &PowerUp, &ActionState, &KinematicCharacterControllerOutput, Has<Crouching>),
+        (With<Player>, Without<Dead>),
+    >,
 ) {
-    if query.is_empty() {
-        return;
+    for (player, transform, power_up, actions, output, crouching) in query.iter() {
+        let wants_to_crouch = actions.pressed(PlayerAction::Crouch) && power_up.can_crouch();
+
+        if !crouching && wants_to_crouch && output.grounded {
+            commands.entity(player).insert(Crouching);
+        } else if crouching && !wants_to_crouch && has_room_to_stand(&rapier_context, player, transform, *power_up) {
+            commands.entity(player).remove::<Crouching>();
+        }
+    }
+}
+
+// Checks the space the standing collider would take above the feet of the crouching one
+fn has_room_to_stand(rapier_context: &RapierContext, player: Entity, transform: &Transform, power_up: PowerUp) -> bool {
+    let scale = transform.scale.truncate();
+    let feet = transform.translation.y - player_height(power_up, true) / 2.0 * scale.y;
+    let height = power_up.height() - STAND_UP_MARGIN;
+    let center = Vec2::new(transform.translation.x, feet + (STAND_UP_MARGIN + height / 2.0) * scale.y);
+    let shape = Collider::cuboid(SPRITE_MARIO_WIDTH / 2.0 * scale.x, height / 2.0 * scale.y);
+    let filter = QueryFilter::default()
+        .exclude_collider(player)
+        .exclude_sensors()
+        .groups(COLLISION_GROUPS_DEFAULT);
+
+    rapier_context.intersection_with_shape(center, 0.0, &shape, filter).is_none()
+}
+
+fn player_height(power_up: PowerUp, crouching: bool) -> f32 {
+    if crouching {
+        power_up.height() / 2.0
+    } else {
+        power_up.height()
     }
+}
+
+// Switches the collider once per power-up or crouch change, keeping the feet where they were
+pub fn resize_player(
+    mut commands: Commands,
+    mut stood_up: RemovedComponents<Crouching>,
+    mut query: Query<(Entity, Ref<PowerUp>, &mut Collider, &mut Transform, Option<Ref<Crouching>>, Has<Dead>)>,
+) {
+    let stood_up: Vec<Entity> = stood_up.iter().collect();
 
-    for (mario_entity, output) in query.iter() {
-        if output.collisions.is_empty() {
-            return;
+    for (player, power_up, mut collider, mut transform, crouching, dead) in query.iter_mut() {
+        let crouched = crouching.as_ref().map_or(false, |crouching| crouching.is_added());
+
+        if !power_up.is_changed() && !crouched && !stood_up.contains(&player) {
+            continue;
         }
-        for event in output.collisions.iter() {
-            for (champi_entity, transform_champi,mut champi) in query_champi.iter_mut() {
-                if champi_entity == event.entity && champi.visible == true {
-                    champi.visible = false;
-                    commands.entity(champi_entity).despawn();
 
-                    commands.entity(mario_entity).insert(Big(0.0));
-                }
-            }
+        let half_height = player_height(*power_up, crouching.is_some()) / 2.0;
+        // rapier scales the collider like the sprite, the new height is not scaled yet
+        let Some(previous_half_height) = collider.as_cuboid().map(|cuboid| cuboid.half_extents().y / collider.scale().y) else { continue };
+
+        if half_height == previous_half_height {
+            continue;
+        }
+
+        transform.translation.y += (half_height - previous_half_height) * transform.scale.y;
+        *collider = Collider::cuboid(SPRITE_MARIO_WIDTH / 2.0, half_height);
+
+        if power_up.is_changed() && !power_up.is_added() && !dead {
+            commands.entity(player).insert(Growing::default());
         }
     }
 }
 
-pub fn add_big_mario(
+pub fn die(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut Handle<TextureAtlas>), With<Big>>,
-    mut atlases: ResMut<Assets<TextureAtlas>>,
-    server: Res<AssetServer>,
+    mut query: Query<(Entity, &mut PowerUp), Added<Dead>>,
 ) {
-    for (mut mario_entity, mut mario_texture_atlas) in query.iter_mut() {
-        let image_handle: Handle<Image> = server.get_handle("spritesheets/spritesheet_Mario.png");
-        let texture_atlas = TextureAtlas::from_grid(
-            image_handle,
-            Vec2::new(SPRITE_MARIO_WIDTH, SPRITE_BIG_MARIO_HEIGHT),
-            SPRITESHEET_COLS,
-            SPRITESHEET_ROWS,
-            Option::from(Vec2::new(SPRITE_PADDING_X, SPRITE_PADDING_Y)),
-            Option::from(Vec2::new(SPRITE_OFFSET_X, 584.0)),
-        );
-        let atlas_handle = atlases.add(texture_atlas);
-
-        *mario_texture_atlas = atlas_handle;
-
-        commands.entity(mario_entity).remove::<Collider>();
-        commands.entity(mario_entity).insert(Collider::cuboid(
-            SPRITE_MARIO_WIDTH / 2.0,
-            SPRITE_BIG_MARIO_HEIGHT / 2.0,
-        ));
+    for (player, mut power_up) in query.iter_mut() {
+        commands
+            .entity(player)
+            .remove::<Jump>()
+            .remove::<Growing>()
+            .remove::<Crouching>()
+            .remove::<Invincible>()
+            .remove::<Starman>();
+
+        // power-ups are lost with the life
+        *power_up = PowerUp::Small;
+    }
+}
+
+// Players come back next to a partner that is still alive, or at the respawn point
+pub fn respawn(
+    mut commands: Commands,
+    time: Res<Time>,
+    respawn_point: Res<RespawnPoint>,
+    mut query: Query<(Entity, &mut Dead, &Lives, &mut Transform, &mut KinematicVelocity, &mut Visibility)>,
+    partners: Query<&Transform, (With<Player>, Without<Dead>)>,
+) {
+    for (player, mut dead, lives, mut transform, mut velocity, mut visibility) in query.iter_mut() {
+        if !dead.0.tick(time.delta()).just_finished() {
+            continue;
+        }
+
+        // without lives left the player stays out of the game until it is continued, see check_game_over
+        if lives.0 == 0 {
+            *visibility = Visibility::Hidden;
+            commands
+                .entity(player)
+                .remove::<(Collider, KinematicCharacterController, KinematicCharacterControllerOutput)>();
+            continue;
+        }
+
+        let position = partners
+            .iter()
+            .next()
+            .map_or(respawn_point.0, |partner| partner.translation.truncate());
+
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        velocity.0 = Vec2::ZERO;
+
+        commands.entity(player).remove::<Dead>();
     }
 }
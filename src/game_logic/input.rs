@@ -0,0 +1,235 @@
+use std::fs;
+use bevy::asset::FileAssetIo;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::Deserialize;
+use crate::game_logic::entities::mario::Player;
+
+// Relative to the folder the asset server loads the assets folder from, whatever the working directory
+const BINDINGS_PATH: &str = "assets/config/bindings.ron";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub enum PlayerAction {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Run,
+    Crouch,
+}
+
+#[derive(Deserialize)]
+pub struct PlayerBindings {
+    pub keyboard: HashMap<PlayerAction, Vec<KeyCode>>,
+    pub gamepad: HashMap<PlayerAction, Vec<GamepadButtonType>>,
+}
+
+// One entry per player, the n-th connected gamepad drives the n-th player
+#[derive(Resource, Deserialize)]
+pub struct InputBindings {
+    pub players: Vec<PlayerBindings>,
+    pub stick_dead_zone: f32,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            players: vec![
+                PlayerBindings {
+                    keyboard: HashMap::from([
+                        (PlayerAction::MoveLeft, vec![KeyCode::A]),
+                        (PlayerAction::MoveRight, vec![KeyCode::D]),
+                        (PlayerAction::Jump, vec![KeyCode::W, KeyCode::Space]),
+                        (PlayerAction::Run, vec![KeyCode::ShiftLeft]),
+                        (PlayerAction::Crouch, vec![KeyCode::S]),
+                    ]),
+                    gamepad: default_gamepad_bindings(),
+                },
+                PlayerBindings {
+                    keyboard: HashMap::from([
+                        (PlayerAction::MoveLeft, vec![KeyCode::Left]),
+                        (PlayerAction::MoveRight, vec![KeyCode::Right]),
+                        (PlayerAction::Jump, vec![KeyCode::Up]),
+                        (PlayerAction::Run, vec![KeyCode::ShiftRight]),
+                        (PlayerAction::Crouch, vec![KeyCode::Down]),
+                    ]),
+                    gamepad: default_gamepad_bindings(),
+                },
+            ],
+            stick_dead_zone: 0.3,
+        }
+    }
+}
+
+fn default_gamepad_bindings() -> HashMap<PlayerAction, Vec<GamepadButtonType>> {
+    HashMap::from([
+        (PlayerAction::MoveLeft, vec![GamepadButtonType::DPadLeft]),
+        (PlayerAction::MoveRight, vec![GamepadButtonType::DPadRight]),
+        (PlayerAction::Jump, vec![GamepadButtonType::South]),
+        (PlayerAction::Run, vec![GamepadButtonType::West]),
+        (PlayerAction::Crouch, vec![GamepadButtonType::DPadDown]),
+    ])
+}
+
+impl InputBindings {
+    // Read once while the app is built, with a blocking read rather than through the asset server,
+    // which only loads in the background while the players need their bindings from the first frame
+    fn load() -> Self {
+        let path = FileAssetIo::get_base_path().join(BINDINGS_PATH);
+        let bindings = fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|content| ron::from_str(&content).map_err(|error| error.to_string()));
+
+        match bindings {
+            Ok(bindings) => bindings,
+            Err(error) => {
+                warn!("Could not load {}, using the default bindings: {}", path.display(), error);
+                Self::default()
+            }
+        }
+    }
+}
+
+// Actions currently requested for a player, gameplay systems only read this component
+// so that the keyboard and gamepads drive the player the same way
+#[derive(Component, Default)]
+pub struct ActionState {
+    pressed: HashSet<PlayerAction>,
+    previous: HashSet<PlayerAction>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: PlayerAction) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: PlayerAction) -> bool {
+        self.pressed.contains(&action) && !self.previous.contains(&action)
+    }
+
+    fn update(&mut self, pressed: HashSet<PlayerAction>) {
+        self.previous = std::mem::replace(&mut self.pressed, pressed);
+    }
+}
+
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(InputBindings::load())
+            .init_resource::<JoinedPlayers>()
+            .add_event::<PlayerJoined>()
+            .add_systems(PreUpdate, (read_player_input, join_players).after(InputSystem));
+    }
+}
+
+// Sent when a player without a character presses any of its bindings, see spawn_joined_players
+#[derive(Event)]
+pub struct PlayerJoined {
+    pub index: usize,
+}
+
+// How many players are in the game, the first one plays from the start and the next ones join one at a time
+#[derive(Resource)]
+pub struct JoinedPlayers(pub usize);
+
+impl Default for JoinedPlayers {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+fn read_player_input(
+    bindings: Res<InputBindings>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut query: Query<(&Player, &mut ActionState)>,
+) {
+    if query.is_empty() {
+        return;
+    }
+
+    let gamepads = sorted_gamepads(&gamepads);
+
+    for (player, mut action_state) in query.iter_mut() {
+        let pressed = pressed_actions(player.index, &bindings, &keyboard, &gamepads, &gamepad_buttons, &gamepad_axes);
+        action_state.update(pressed);
+    }
+}
+
+fn join_players(
+    bindings: Res<InputBindings>,
+    joined: Res<JoinedPlayers>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut player_joined: EventWriter<PlayerJoined>,
+) {
+    let index = joined.0;
+    if index >= bindings.players.len() {
+        return;
+    }
+
+    let gamepads = sorted_gamepads(&gamepads);
+
+    if !pressed_actions(index, &bindings, &keyboard, &gamepads, &gamepad_buttons, &gamepad_axes).is_empty() {
+        player_joined.send(PlayerJoined { index });
+    }
+}
+
+// The n-th gamepad is the one with the n-th lowest id
+fn sorted_gamepads(gamepads: &Gamepads) -> Vec<Gamepad> {
+    let mut gamepads: Vec<Gamepad> = gamepads.iter().collect();
+    gamepads.sort_by_key(|gamepad| gamepad.id);
+    gamepads
+}
+
+fn pressed_actions(
+    index: usize,
+    bindings: &InputBindings,
+    keyboard: &Input<KeyCode>,
+    gamepads: &[Gamepad],
+    gamepad_buttons: &Input<GamepadButton>,
+    gamepad_axes: &Axis<GamepadAxis>,
+) -> HashSet<PlayerAction> {
+    let mut pressed = HashSet::new();
+
+    let Some(player_bindings) = bindings.players.get(index) else { return pressed };
+
+    for (action, keys) in player_bindings.keyboard.iter() {
+        if keyboard.any_pressed(keys.iter().copied()) {
+            pressed.insert(*action);
+        }
+    }
+
+    if let Some(gamepad) = gamepads.get(index).copied() {
+        for (action, buttons) in player_bindings.gamepad.iter() {
+            if gamepad_buttons.any_pressed(buttons.iter().map(|button| GamepadButton::new(gamepad, *button))) {
+                pressed.insert(*action);
+            }
+        }
+
+        let stick_x = gamepad_axes
+            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+            .unwrap_or(0.0);
+        let stick_y = gamepad_axes
+            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+            .unwrap_or(0.0);
+
+        if stick_x < -bindings.stick_dead_zone {
+            pressed.insert(PlayerAction::MoveLeft);
+        }
+        if stick_x > bindings.stick_dead_zone {
+            pressed.insert(PlayerAction::MoveRight);
+        }
+        if stick_y < -bindings.stick_dead_zone {
+            pressed.insert(PlayerAction::Crouch);
+        }
+    }
+
+    pressed
+}
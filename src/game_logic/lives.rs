@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use crate::game_logic::entities::coin::Coins;
+use crate::game_logic::entities::mario::{Dead, Player};
+use crate::game_logic::input::{ActionState, JoinedPlayers, PlayerAction};
+use crate::game_logic::world::level::LevelRestarted;
+use crate::rendering::sprite_manager::{PlayerAtlases, spawn_players};
+
+const START_LIVES: u32 = 3;
+
+#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum GameState {
+    #[default]
+    Playing,
+    GameOver,
+}
+
+#[derive(Component)]
+pub struct Lives(pub u32);
+
+impl Default for Lives {
+    fn default() -> Self {
+        Self(START_LIVES)
+    }
+}
+
+pub struct LivesPlugin;
+
+impl Plugin for LivesPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_state::<GameState>()
+            .add_systems(Update, (lose_life, check_game_over).run_if(in_state(GameState::Playing)))
+            .add_systems(Update, continue_game.run_if(in_state(GameState::GameOver)));
+    }
+}
+
+fn lose_life(mut query: Query<&mut Lives, (With<Player>, Added<Dead>)>) {
+    for mut lives in query.iter_mut() {
+        lives.0 = lives.0.saturating_sub(1);
+    }
+}
+
+// The game is over once every player lost its last life and its death sequence has ended
+fn check_game_over(
+    query: Query<(&Lives, Option<&Dead>), With<Player>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if query.is_empty() {
+        return;
+    }
+
+    let game_over = query.iter().all(|(lives, dead)| {
+        lives.0 == 0 && dead.map_or(false, |dead| dead.finished())
+    });
+
+    if game_over {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+// Pressing jump on the game over screen restarts the current level with fresh players
+fn continue_game(
+    mut commands: Commands,
+    player_atlases: Res<PlayerAtlases>,
+    joined: Res<JoinedPlayers>,
+    mut coins: ResMut<Coins>,
+    query: Query<(Entity, &ActionState), With<Player>>,
+    mut level_restarted: EventWriter<LevelRestarted>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !query.iter().any(|(_, actions)| actions.just_pressed(PlayerAction::Jump)) {
+        return;
+    }
+
+    for (player, _) in query.iter() {
+        commands.entity(player).despawn_recursive();
+    }
+
+    // players that joined during the game stay in it
+    spawn_players(&mut commands, &player_atlases, joined.0);
+    *coins = Coins::default();
+
+    level_restarted.send(LevelRestarted);
+    next_state.set(GameState::Playing);
+}
@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::entities::mario::{Dead, Player};
+
+#[derive(Component)]
+pub struct KillZone;
+
+#[derive(Bundle)]
+pub struct KillZoneFactory {
+    kill_zone: KillZone,
+    transform_bundle: TransformBundle,
+    body: RigidBody,
+    collider: Collider,
+    sensor: Sensor,
+    active_collision_types: ActiveCollisionTypes,
+}
+
+impl KillZoneFactory {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            kill_zone: KillZone,
+            transform_bundle: TransformBundle::from(Transform::from_xyz(x, y, 0.0)),
+            body: RigidBody::Fixed,
+            collider: Collider::cuboid(width / 2.0, height / 2.0),
+            sensor: Sensor,
+            active_collision_types: ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        }
+    }
+}
+
+pub fn detect_fall_in_kill_zone(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    players: Query<Entity, (With<Player>, Without<Dead>)>,
+    kill_zones: Query<Entity, With<KillZone>>,
+) {
+    if players.is_empty() {
+        return;
+    }
+
+    for player in players.iter() {
+        for kill_zone in kill_zones.iter() {
+            if rapier_context.intersection_pair(player, kill_zone) == Some(true) {
+                commands.entity(player).insert(Dead::default());
+            }
+        }
+    }
+}
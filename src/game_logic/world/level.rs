@@ -0,0 +1,331 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+use crate::game_logic::world::physics::world_to_vec;
+use crate::game_logic::world::platform::{GROUP_ONE_WAY, OneWayPlatform};
+use crate::game_logic::world::kill_zone::KillZoneFactory;
+use crate::game_logic::world::tiled::TiledLoader;
+use crate::utils::build_point;
+use crate::{SCALE, WINDOW_WIDTH};
+
+// Depth of the kill zone spanning the whole level below its bottom edge
+const KILL_ZONE_HEIGHT: f32 = 100.0;
+
+// Levels are played in this order, the game starts over from the first one after the last
+const LEVELS: &[&str] = &[
+    "levels/1-1.tmj",
+    "levels/1-2.level.ron",
+];
+
+// Every position is expressed in background pixels, with the origin in the bottom left corner (see build_point)
+#[derive(Deserialize, TypeUuid, TypePath)]
+#[uuid = "5d3c1a4e-8f6b-4c2a-9e71-0b8d2f4a6c13"]
+pub struct Level {
+    #[serde(default)]
+    pub background: Option<String>,
+    pub size: (f32, f32),
+    pub spawn: (f32, f32),
+    // Bottom of the flagpole that ends the level
+    #[serde(default)]
+    pub goal: Option<(f32, f32)>,
+    // Bottom of each checkpoint, touching one moves the respawn point there
+    #[serde(default)]
+    pub checkpoints: Vec<(f32, f32)>,
+    pub terrain: Vec<Vec<(f32, f32)>>,
+    // One-way platforms can be crossed from below and landed on from above
+    #[serde(default)]
+    pub platforms: Vec<Vec<(f32, f32)>>,
+    pub blocks: Vec<BlockSpawn>,
+    // Center of each coin that can be collected by touching it
+    #[serde(default)]
+    pub coins: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub enemies: Vec<EnemySpawn>,
+    #[serde(default)]
+    pub tileset: Option<TilesetSpawn>,
+    #[serde(default)]
+    pub tiles: Vec<TileSpawn>,
+}
+
+#[derive(Deserialize)]
+pub struct BlockSpawn {
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub kind: BlockKind,
+    #[serde(default)]
+    pub contents: BlockContents,
+}
+
+// A brick with nothing inside breaks when hit by big Mario, otherwise it behaves like a ? block.
+// A hidden block can't be seen nor touched until it is hit from below.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlockKind {
+    #[default]
+    Question,
+    Brick,
+    Hidden,
+}
+
+// What comes out of a block when it is hit from below
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlockContents {
+    #[default]
+    Coin,
+    // Gives a coin on every hit for a few seconds, this many at most
+    MultiCoin(u32),
+    Mushroom,
+    FireFlower,
+    Star,
+    OneUp,
+    Empty,
+}
+
+impl BlockContents {
+    pub fn item(self) -> Option<ItemKind> {
+        match self {
+            BlockContents::Mushroom => Some(ItemKind::Mushroom),
+            BlockContents::FireFlower => Some(ItemKind::FireFlower),
+            BlockContents::Star => Some(ItemKind::Star),
+            BlockContents::OneUp => Some(ItemKind::OneUp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Mushroom,
+    FireFlower,
+    Star,
+    OneUp,
+}
+
+// Enemies start walking towards the left from their center
+#[derive(Deserialize)]
+pub struct EnemySpawn {
+    pub kind: EnemyKind,
+    pub position: (f32, f32),
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum EnemyKind {
+    Goomba,
+    Koopa,
+}
+
+#[derive(Deserialize)]
+pub struct TilesetSpawn {
+    pub image: String,
+    pub tile_size: (f32, f32),
+    pub columns: usize,
+    pub rows: usize,
+    pub spacing: f32,
+    pub margin: f32,
+}
+
+#[derive(Deserialize)]
+pub struct TileSpawn {
+    pub index: usize,
+    // Tiles of a later layer are drawn over the ones of an earlier layer
+    #[serde(default)]
+    pub layer: usize,
+    pub position: (f32, f32),
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level: Level = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[derive(Resource)]
+pub struct LevelRegistry {
+    pub levels: Vec<String>,
+    pub current: usize,
+}
+
+impl Default for LevelRegistry {
+    fn default() -> Self {
+        Self {
+            levels: LEVELS.iter().map(|path| path.to_string()).collect(),
+            current: 0,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct CurrentLevel {
+    pub handle: Handle<Level>,
+    spawned: bool,
+}
+
+// Sent once the current level file is loaded and its terrain is spawned
+#[derive(Event)]
+pub struct LevelLoaded;
+
+#[derive(Event)]
+pub struct LevelCompleted;
+
+// Respawns everything in the current level, as if it was entered again
+#[derive(Event)]
+pub struct LevelRestarted;
+
+// Everything that belongs to a level and is despawned when leaving it
+#[derive(Component)]
+pub struct LevelEntity;
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .init_asset_loader::<TiledLoader>()
+            .init_resource::<LevelRegistry>()
+            .add_event::<LevelLoaded>()
+            .add_event::<LevelCompleted>()
+            .add_event::<LevelRestarted>()
+            .add_systems(Startup, load_first_level)
+            .add_systems(Update, (spawn_terrain, load_next_level, restart_level));
+    }
+}
+
+fn load_first_level(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    registry: Res<LevelRegistry>,
+) {
+    commands.insert_resource(CurrentLevel {
+        handle: server.load(registry.levels[registry.current].as_str()),
+        spawned: false,
+    });
+}
+
+fn load_next_level(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    mut level_completed: EventReader<LevelCompleted>,
+    mut registry: ResMut<LevelRegistry>,
+    mut current_level: ResMut<CurrentLevel>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+) {
+    if level_completed.is_empty() {
+        return;
+    }
+    level_completed.clear();
+
+    unload_level(&mut commands, &level_entities);
+
+    registry.current = (registry.current + 1) % registry.levels.len();
+
+    current_level.handle = server.load(registry.levels[registry.current].as_str());
+    current_level.spawned = false;
+}
+
+fn restart_level(
+    mut commands: Commands,
+    mut level_restarted: EventReader<LevelRestarted>,
+    mut current_level: ResMut<CurrentLevel>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+) {
+    if level_restarted.is_empty() {
+        return;
+    }
+    level_restarted.clear();
+
+    unload_level(&mut commands, &level_entities);
+
+    // the level asset is still loaded, spawn_terrain picks it up again
+    current_level.spawned = false;
+}
+
+fn unload_level(commands: &mut Commands, level_entities: &Query<Entity, With<LevelEntity>>) {
+    for entity in level_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_terrain(
+    mut commands: Commands,
+    mut current_level: ResMut<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut level_loaded: EventWriter<LevelLoaded>,
+) {
+    if current_level.spawned {
+        return;
+    }
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+
+    let (vertices, indices) = world_to_vec(&level.terrain);
+
+    commands
+        .spawn(SpriteBundle {
+            transform: Transform {
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RigidBody::Fixed)
+        .insert(Collider::polyline(vertices, Option::from(indices)))
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(CollisionGroups::new(
+            Group::GROUP_1,
+            Group::ALL
+        ))
+        .insert(LevelEntity);
+
+    let bottom_center = build_point(level.size.0 / 2.0, 0.0);
+
+    commands
+        .spawn(KillZoneFactory::new(
+            bottom_center.x,
+            bottom_center.y - KILL_ZONE_HEIGHT,
+            level.size.0 * SCALE + WINDOW_WIDTH,
+            KILL_ZONE_HEIGHT,
+        ))
+        .insert(LevelEntity);
+
+    for platform in level.platforms.iter() {
+        let (vertices, indices) = world_to_vec(std::slice::from_ref(platform));
+        let top = vertices.iter().map(|vertex| vertex.y).fold(f32::MIN, f32::max);
+
+        commands
+            .spawn(SpriteBundle {
+                transform: Transform {
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(RigidBody::Fixed)
+            .insert(Collider::polyline(vertices, Option::from(indices)))
+            .insert(CollisionGroups::new(
+                GROUP_ONE_WAY,
+                Group::ALL
+            ))
+            .insert(OneWayPlatform { top })
+            .insert(LevelEntity);
+    }
+
+    current_level.spawned = true;
+    level_loaded.send(LevelLoaded);
+}
@@ -1,102 +1,182 @@
 pub struct PhysicsPlugin;
 
-use crate::{BG_WIDTH};
 use bevy::prelude::*;
-use crate::game_logic::entities::block::{detect_collision_from_below_on_block};
-use crate::game_logic::entities::champi::{apply_translation_to_champi};
-use crate::game_logic::entities::mario::{add_big_mario, apply_movement_animation, detect_collision_with_champi, fall, jump, movement, rise, update_direction};
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::world::platform::resolve_one_way_platforms;
+use crate::game_logic::entities::block::{BlockHit, bump_blocks, detect_collision_from_below_on_block, move_debris, open_block, resolve_hidden_blocks, tick_multi_coin_timers};
+use crate::game_logic::entities::goal::reach_goal;
+use crate::game_logic::entities::champi::release_emerged_champi;
+use crate::game_logic::entities::emerging::emerge;
+use crate::game_logic::entities::enemy::{clear_squashed, collide_enemies, fall_knocked_out, touch_enemies, touch_shells};
+use crate::game_logic::entities::coin::{CoinCollected, Coins, collect_coins, count_coins, pop_coins};
+use crate::game_logic::entities::fireball::{move_fireballs, throw_fireball};
+use crate::game_logic::entities::checkpoint::{reach_checkpoint, reset_respawn_point, RespawnPoint};
+use crate::game_logic::entities::mario::{crouch, Direction, detect_collision_with_champi, detect_head_bump, Dead, die, HeadBump, jump, movement, PlayerDamaged, Jump, resize_player, respawn, rise, take_damage, tick_invincibility, track_jump_timing, update_direction, update_player_state};
+use crate::game_logic::world::kill_zone::detect_fall_in_kill_zone;
+use crate::game_logic::world::tuning::MovementTuning;
 use crate::utils::build_point;
 
 const SPRITE_TILE_WIDTH: f32 = 16.0;
 const SPRITE_TILE_HEIGHT: f32 = 32.0;
 
+// Velocity of a kinematic body in pixels per second, turned into a character controller translation every frame
+#[derive(Component, Default)]
+pub struct KinematicVelocity(pub Vec2);
+
+// Kinematic bodies pulled down by apply_gravity
+#[derive(Component)]
+pub struct Gravity;
+
+// Kinematic bodies walking at this speed in their Direction, turning around when they run into a wall
+#[derive(Component)]
+pub struct Walker(pub f32);
+
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
         app
+            .init_resource::<RespawnPoint>()
+            .init_resource::<MovementTuning>()
+            .init_resource::<Coins>()
+            .add_event::<PlayerDamaged>()
+            .add_event::<HeadBump>()
+            .add_event::<BlockHit>()
+            .add_event::<CoinCollected>()
             .add_systems(
             Update,
             (
                 movement,
-                jump,
-                rise,
-                fall,
-                apply_movement_animation,
+                track_jump_timing,
+                jump.after(track_jump_timing),
+                detect_head_bump.after(jump),
+                rise.after(detect_head_bump),
+                apply_gravity.after(rise),
+                apply_kinematic_velocity.after(apply_gravity).after(movement),
+                resolve_one_way_platforms.after(apply_kinematic_velocity),
+                update_player_state.after(movement).after(jump),
                 update_direction,
-                detect_collision_from_below_on_block,
-                apply_translation_to_champi,
+                detect_collision_from_below_on_block.after(detect_head_bump),
+                release_emerged_champi,
                 detect_collision_with_champi,
-                add_big_mario
+                reach_goal,
+                reset_respawn_point,
+                reach_checkpoint,
+                detect_fall_in_kill_zone,
+            ),
+        )
+            .add_systems(
+            Update,
+            (
+                take_damage,
+                tick_invincibility,
+                throw_fireball,
+                move_fireballs.before(apply_kinematic_velocity),
+                emerge,
+                walk.before(apply_kinematic_velocity),
+                pop_coins,
+                move_debris,
+                open_block.after(detect_collision_from_below_on_block),
+                bump_blocks.after(open_block).before(apply_kinematic_velocity),
+                tick_multi_coin_timers,
+                resolve_hidden_blocks.after(apply_kinematic_velocity),
+                collect_coins,
+                count_coins.after(collect_coins).after(open_block),
+                crouch,
+                die.after(take_damage),
+                resize_player.after(die),
+                respawn
+            ),
+        )
+            .add_systems(
+            Update,
+            (
+                touch_enemies.after(rise).before(apply_gravity),
+                touch_shells.after(rise).before(apply_gravity),
+                collide_enemies.before(walk),
+                clear_squashed,
+                fall_knocked_out,
             ),
         );
     }
 }
 
-pub fn world_to_vec() -> (Vec<Vec2>, Vec<[u32; 2]>) {
-    let platform_3 = vec![ // Create factories to create each element (pipe, platform, etc)
-                           build_point(2992.0, 96.0),
-                           build_point(3071.0, 96.0),
-    ];
-
-    let platform_4 = [
-        build_point(3024.0, 144.0),
-        build_point(3150.0, 144.0),
-    ];
-
-    let platform_5 = [
-        build_point(3120.0, 96.0),
-        build_point(3215.0, 96.0),
-    ];
-
-    let platform_6 = [
-        build_point(3392.0, 128.0),
-        build_point(3551.0, 128.0),
-    ];
-
-    let mut vertices = vec![
-        build_point(0.0, 47.0),
-        build_point(1872.0, 47.0),
-        build_point(304.0, 95.0),
-        build_point(558.0, 95.0),
-        build_point(704.0, 112.0),
-        build_point(815.0, 112.0),
-        build_point(1872.0, 64.0),
-        build_point(1952.0, 64.0),
-        build_point(1952.0, 80.0),
-        build_point(2015.0, 80.0),
-        build_point(2015.0, 96.0),
-        build_point(2064.0, 96.0),
-        build_point(2064.0, 113.0),
-        build_point(2287.0, 113.0),
-        build_point(2287.0, 47.0),
-        build_point(3488.0, 47.0),
-        build_point(3488.0, 79.0),
-        build_point(3647.0, 79.0),
-        build_point(3647.0, 47.0),
-        build_point(BG_WIDTH, 47.0),
-    ];
-
-    vertices.extend(platform_3);
-    vertices.extend(platform_4);
-    vertices.extend(platform_5);
-    vertices.extend(platform_6);
-
-    let indices = vec![
-        [0u32, 1u32],
-        [2u32, 3u32],
-        [4u32, 5u32],
-        [6u32, 7u32],
-        [8u32, 9u32],
-        [10u32, 11u32],
-        [12u32, 13u32],
-        [13u32, 14u32],
-        [14u32, 15u32],
-        [16u32, 17u32],
-        [18u32, 19u32],
-        [20u32, 21u32],
-        [22u32, 23u32],
-        [24u32, 25u32],
-        [26u32, 27u32]
-    ];
+pub fn world_to_vec(polylines: &[Vec<(f32, f32)>]) -> (Vec<Vec2>, Vec<[u32; 2]>) {
+    let mut vertices = vec![];
+    let mut indices = vec![];
+
+    for polyline in polylines.iter() { // each polyline is a chain of connected points
+        let first = vertices.len() as u32;
+
+        vertices.extend(polyline.iter().map(|(x, y)| build_point(*x, *y)));
+
+        for i in 1..polyline.len() as u32 {
+            indices.push([first + i - 1, first + i]);
+        }
+    }
 
     return (vertices, indices);
 }
+
+pub fn apply_gravity(
+    time: Res<Time>,
+    tuning: Res<MovementTuning>,
+    mut query: Query<
+        (&mut KinematicVelocity, Option<&KinematicCharacterControllerOutput>, Has<Jump>),
+        (With<Gravity>, Without<Dead>),
+    >,
+) {
+    for (mut velocity, output, jumping) in query.iter_mut() {
+        // standing on the ground, only keep pressing against it so that grounded stays detected
+        if output.map_or(false, |output| output.grounded) && velocity.0.y < 0.0 {
+            velocity.0.y = 0.0;
+        }
+
+        let scale = if jumping && velocity.0.y.abs() < tuning.apex_threshold {
+            tuning.apex_gravity_scale
+        } else {
+            1.0
+        };
+
+        velocity.0.y = (velocity.0.y - tuning.gravity * scale * time.delta_seconds()).max(-tuning.terminal_velocity);
+    }
+}
+
+pub fn apply_kinematic_velocity(
+    time: Res<Time>,
+    mut query: Query<(&KinematicVelocity, &mut KinematicCharacterController), Without<Dead>>,
+) {
+    for (velocity, mut controller) in query.iter_mut() {
+        controller.translation = Some(velocity.0 * time.delta_seconds());
+    }
+}
+
+pub fn walk(
+    mut query: Query<(&Walker, &mut Direction, &mut KinematicVelocity, Option<&KinematicCharacterControllerOutput>)>,
+) {
+    for (walker, mut direction, mut velocity, output) in query.iter_mut() {
+        let blocked = output.map_or(false, |output| {
+            output.desired_translation.x != 0.0
+                && output.effective_translation.x.abs() < output.desired_translation.x.abs() / 2.0
+        });
+
+        if blocked {
+            *direction = match *direction {
+                Direction::Right => Direction::Left,
+                Direction::Left => Direction::Right,
+            };
+        }
+
+        velocity.0.x = match *direction {
+            Direction::Right => walker.0,
+            Direction::Left => -walker.0,
+        };
+    }
+}
+
+// Moves value towards target by at most step
+pub fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
+    }
+}
@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use crate::game_logic::entities::mario::Player;
+
+// One-way platforms always belong to GROUP_ONE_WAY, which players do not collide with, so that every
+// other body keeps landing on them. Each player has its own solid group, that a platform also belongs
+// to while this player stands above it, which is the only moment this player collides with it.
+pub const GROUP_ONE_WAY: Group = Group::GROUP_4;
+// Indexed by Player::index
+const GROUPS_ONE_WAY_SOLID: [Group; 2] = [Group::GROUP_5, Group::GROUP_8];
+pub const GROUP_ONE_WAY_SOLID_ANY: Group = Group::GROUP_5.union(Group::GROUP_8);
+
+// Only absorbs rounding errors, a player whose feet are any lower is inside the platform and falls through
+const LANDING_TOLERANCE: f32 = 0.5;
+
+#[derive(Component)]
+pub struct OneWayPlatform {
+    pub top: f32,
+}
+
+pub fn one_way_solid_group(player: &Player) -> Group {
+    GROUPS_ONE_WAY_SOLID.get(player.index).copied().unwrap_or(Group::NONE)
+}
+
+// Every player only collides with the platforms it stands above
+pub fn resolve_one_way_platforms(
+    players: Query<(&Player, &Transform, &Collider, &KinematicCharacterController)>,
+    mut platforms: Query<(&OneWayPlatform, &mut CollisionGroups)>,
+) {
+    if platforms.is_empty() {
+        return;
+    }
+
+    for (platform, mut groups) in platforms.iter_mut() {
+        let mut memberships = GROUP_ONE_WAY;
+
+        for (player, transform, collider, controller) in players.iter() {
+            let half_height = collider.as_cuboid().map_or(0.0, |cuboid| cuboid.half_extents().y);
+            let feet = transform.translation.y - half_height;
+            let rising = controller.translation.map_or(false, |translation| translation.y > 0.0);
+
+            if !rising && feet >= platform.top - LANDING_TOLERANCE {
+                memberships |= one_way_solid_group(player);
+            }
+        }
+
+        if groups.memberships != memberships {
+            groups.memberships = memberships;
+        }
+    }
+}
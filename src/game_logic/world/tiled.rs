@@ -0,0 +1,641 @@
+use std::path::{Component, Path, PathBuf};
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use crate::game_logic::world::level::{BlockContents, BlockKind, BlockSpawn, EnemyKind, EnemySpawn, Level, TileSpawn, TilesetSpawn};
+
+// Bits used by Tiled to store the flip state of a tile in its gid
+const FLIPPED_FLAGS: u32 = 0xE000_0000;
+
+const OBJECT_BLOCK: &str = "block";
+const OBJECT_BRICK: &str = "brick";
+const OBJECT_HIDDEN_BLOCK: &str = "hidden_block";
+const OBJECT_COIN: &str = "coin";
+const OBJECT_GOOMBA: &str = "goomba";
+const OBJECT_KOOPA: &str = "koopa";
+const OBJECT_SPAWN: &str = "spawn";
+const OBJECT_GOAL: &str = "goal";
+const OBJECT_CHECKPOINT: &str = "checkpoint";
+const OBJECT_ONE_WAY: &str = "one_way";
+
+#[derive(Deserialize)]
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tilewidth: f32,
+    tileheight: f32,
+    // Infinite maps store their tiles in chunks instead of a single grid
+    #[serde(default)]
+    infinite: bool,
+    layers: Vec<TiledLayer>,
+    #[serde(default)]
+    tilesets: Vec<TiledTileset>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TiledLayer {
+    TileLayer {
+        width: u32,
+        #[serde(default)]
+        data: TiledLayerData,
+        chunks: Option<serde_json::Value>,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+    },
+    ObjectGroup {
+        objects: Vec<TiledObject>,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+    },
+    ImageLayer {
+        image: String,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+    },
+    Group {
+        layers: Vec<TiledLayer>,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+    },
+}
+
+impl TiledLayer {
+    fn offset(&self) -> (f32, f32) {
+        match self {
+            TiledLayer::TileLayer { offsetx, offsety, .. }
+            | TiledLayer::ObjectGroup { offsetx, offsety, .. }
+            | TiledLayer::ImageLayer { offsetx, offsety, .. }
+            | TiledLayer::Group { offsetx, offsety, .. } => (*offsetx, *offsety),
+        }
+    }
+}
+
+// Only the CSV layer format stores plain gids, the other ones store a base64 string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TiledLayerData {
+    Gids(Vec<u32>),
+    Encoded(String),
+}
+
+impl Default for TiledLayerData {
+    fn default() -> Self {
+        TiledLayerData::Gids(vec![])
+    }
+}
+
+#[derive(Deserialize)]
+struct TiledTileset {
+    firstgid: u32,
+    source: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    columns: usize,
+    #[serde(default)]
+    tilecount: usize,
+    #[serde(default)]
+    tilewidth: f32,
+    #[serde(default)]
+    tileheight: f32,
+    #[serde(default)]
+    spacing: f32,
+    #[serde(default)]
+    margin: f32,
+}
+
+#[derive(Deserialize)]
+struct TiledObject {
+    id: i32,
+    #[serde(default)]
+    name: String,
+    // Tiled 1.9 renamed the object "type" to "class"
+    #[serde(default, rename = "type", alias = "class")]
+    kind: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    gid: Option<u32>,
+    polyline: Option<Vec<TiledPoint>>,
+    polygon: Option<Vec<TiledPoint>>,
+    #[serde(default)]
+    point: bool,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledPoint {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Deserialize)]
+struct TiledProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+impl TiledObject {
+    fn is(&self, kind: &str) -> bool {
+        self.name == kind || self.kind == kind
+    }
+
+    fn bool_property(&self, name: &str) -> bool {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+            .and_then(|property| property.value.as_bool())
+            .unwrap_or(false)
+    }
+
+    fn string_property(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+            .and_then(|property| property.value.as_str())
+    }
+
+    fn int_property(&self, name: &str) -> Option<i32> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+            .and_then(|property| property.value.as_i64())
+            .map(|value| value as i32)
+    }
+
+    // Tile objects are anchored on their bottom left corner, every other object on its top left corner
+    fn center(&self) -> (f32, f32) {
+        match self.gid {
+            Some(_) => (self.x + self.width / 2.0, self.y - self.height / 2.0),
+            None => (self.x + self.width / 2.0, self.y + self.height / 2.0),
+        }
+    }
+
+    fn bottom(&self) -> (f32, f32) {
+        match self.gid {
+            Some(_) => (self.x + self.width / 2.0, self.y),
+            None => (self.x + self.width / 2.0, self.y + self.height),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let map: TiledMap = serde_json::from_slice(bytes)?;
+            let directory = load_context.path().parent().unwrap_or(Path::new("")).to_path_buf();
+            let level = map_to_level(map, &directory)?;
+
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmj"]
+    }
+}
+
+fn map_to_level(map: TiledMap, directory: &Path) -> Result<Level, bevy::asset::Error> {
+    let map_height = map.height as f32 * map.tileheight;
+
+    // Tiled puts the origin in the top left corner, levels in the bottom left one
+    let flip_y = |(x, y): (f32, f32)| (x, map_height - y);
+
+    let mut level = Level {
+        background: None,
+        size: (map.width as f32 * map.tilewidth, map_height),
+        spawn: (0.0, 0.0),
+        goal: None,
+        checkpoints: vec![],
+        terrain: vec![],
+        platforms: vec![],
+        blocks: vec![],
+        coins: vec![],
+        enemies: vec![],
+        tileset: None,
+        tiles: vec![],
+    };
+
+    if map.infinite {
+        return Err(bevy::asset::Error::msg("infinite maps are not supported, give the map a fixed size"));
+    }
+
+    let (first_gid, tile_count) = match map.tilesets.as_slice() {
+        [] => (1, 0),
+        [tileset] => {
+            if let Some(source) = &tileset.source {
+                return Err(bevy::asset::Error::msg(format!(
+                    "external tileset {} is not supported, embed it in the map",
+                    source
+                )));
+            }
+
+            let image = tileset.image.as_ref()
+                .ok_or_else(|| bevy::asset::Error::msg("tilesets made of a collection of images are not supported"))?;
+
+            if tileset.columns == 0 {
+                return Err(bevy::asset::Error::msg("the tileset has no columns"));
+            }
+
+            level.tileset = Some(TilesetSpawn {
+                image: asset_path(directory, image),
+                tile_size: (tileset.tilewidth, tileset.tileheight),
+                columns: tileset.columns,
+                rows: (tileset.tilecount + tileset.columns - 1) / tileset.columns,
+                spacing: tileset.spacing,
+                margin: tileset.margin,
+            });
+
+            (tileset.firstgid, tileset.tilecount)
+        }
+        _ => return Err(bevy::asset::Error::msg("maps using more than one tileset are not supported, merge them into one")),
+    };
+
+    let mut spawn = None;
+    let mut tile_layer = 0;
+    // layers are read in file order, with the layers of a group in place of the group
+    let mut layers: Vec<&TiledLayer> = map.layers.iter().rev().collect();
+
+    while let Some(layer) = layers.pop() {
+        if layer.offset() != (0.0, 0.0) {
+            return Err(bevy::asset::Error::msg("layer offsets are not supported, move the layer contents instead"));
+        }
+
+        match layer {
+            TiledLayer::TileLayer { width, data, chunks, .. } => {
+                if chunks.is_some() {
+                    return Err(bevy::asset::Error::msg("chunked tile layers are not supported, give the map a fixed size"));
+                }
+                let TiledLayerData::Gids(data) = data else {
+                    return Err(bevy::asset::Error::msg("encoded tile layers are not supported, save the map with the CSV layer format"));
+                };
+
+                for (i, gid) in data.iter().enumerate() {
+                    let gid = gid & !FLIPPED_FLAGS;
+
+                    if gid < first_gid {
+                        continue; // empty cell
+                    }
+
+                    let index = (gid - first_gid) as usize;
+
+                    if index >= tile_count {
+                        return Err(bevy::asset::Error::msg(format!("tile {} is not part of the tileset", gid)));
+                    }
+
+                    let column = (i as u32 % width) as f32;
+                    let row = (i as u32 / width) as f32;
+
+                    level.tiles.push(TileSpawn {
+                        index,
+                        layer: tile_layer,
+                        position: flip_y((
+                            (column + 0.5) * map.tilewidth,
+                            (row + 0.5) * map.tileheight,
+                        )),
+                    });
+                }
+
+                tile_layer += 1;
+            }
+            TiledLayer::ObjectGroup { objects, .. } => {
+                for object in objects.iter() {
+                    if object.rotation != 0.0 {
+                        return Err(bevy::asset::Error::msg(format!("object {} is rotated, rotations are not supported", object.id)));
+                    }
+
+                    if object.is(OBJECT_SPAWN) {
+                        spawn = Some(flip_y(object.center()));
+                    } else if object.is(OBJECT_GOAL) {
+                        level.goal = Some(flip_y(object.bottom()));
+                    } else if object.is(OBJECT_CHECKPOINT) {
+                        level.checkpoints.push(flip_y(object.bottom()));
+                    } else if object.is(OBJECT_BLOCK) {
+                        level.blocks.push(BlockSpawn {
+                            position: flip_y(object.center()),
+                            kind: BlockKind::Question,
+                            contents: block_contents(object, BlockContents::Coin)?,
+                        });
+                    } else if object.is(OBJECT_HIDDEN_BLOCK) {
+                        level.blocks.push(BlockSpawn {
+                            position: flip_y(object.center()),
+                            kind: BlockKind::Hidden,
+                            contents: block_contents(object, BlockContents::Coin)?,
+                        });
+                    } else if object.is(OBJECT_COIN) {
+                        level.coins.push(flip_y(object.center()));
+                    } else if object.is(OBJECT_GOOMBA) {
+                        level.enemies.push(EnemySpawn {
+                            kind: EnemyKind::Goomba,
+                            position: flip_y(object.center()),
+                        });
+                    } else if object.is(OBJECT_KOOPA) {
+                        level.enemies.push(EnemySpawn {
+                            kind: EnemyKind::Koopa,
+                            position: flip_y(object.center()),
+                        });
+                    } else if object.is(OBJECT_BRICK) {
+                        level.blocks.push(BlockSpawn {
+                            position: flip_y(object.center()),
+                            kind: BlockKind::Brick,
+                            contents: block_contents(object, BlockContents::Empty)?,
+                        });
+                    } else if let Some(polyline) = object_to_polyline(object) {
+                        let polyline = polyline.into_iter().map(flip_y).collect();
+
+                        if object.is(OBJECT_ONE_WAY) || object.bool_property(OBJECT_ONE_WAY) {
+                            level.platforms.push(polyline);
+                        } else {
+                            level.terrain.push(polyline);
+                        }
+                    }
+                }
+            }
+            TiledLayer::ImageLayer { image, .. } => {
+                level.background = Some(asset_path(directory, image));
+            }
+            TiledLayer::Group { layers: children, .. } => {
+                layers.extend(children.iter().rev());
+            }
+        }
+    }
+
+    level.spawn = spawn.ok_or_else(|| bevy::asset::Error::msg("the map has no spawn object"))?;
+
+    Ok(level)
+}
+
+// Read from the contents property of a block, brick or hidden block object
+fn block_contents(object: &TiledObject, default: BlockContents) -> Result<BlockContents, bevy::asset::Error> {
+    Ok(match object.string_property("contents") {
+        None => default,
+        Some("coin") => BlockContents::Coin,
+        Some("multi_coin") => BlockContents::MultiCoin(object.int_property("coins").unwrap_or(10).max(1) as u32),
+        Some("mushroom") => BlockContents::Mushroom,
+        Some("fire_flower") => BlockContents::FireFlower,
+        Some("star") => BlockContents::Star,
+        Some("one_up") => BlockContents::OneUp,
+        Some("empty") => BlockContents::Empty,
+        Some(other) => {
+            return Err(bevy::asset::Error::msg(format!("block object {} has unknown contents {}", object.id, other)));
+        }
+    })
+}
+
+fn object_to_polyline(object: &TiledObject) -> Option<Vec<(f32, f32)>> {
+    let offset = |point: &TiledPoint| (object.x + point.x, object.y + point.y);
+
+    if let Some(points) = &object.polyline {
+        return Some(points.iter().map(offset).collect());
+    }
+
+    if let Some(points) = object.polygon.as_ref().filter(|points| !points.is_empty()) {
+        let mut polyline: Vec<(f32, f32)> = points.iter().map(offset).collect();
+        polyline.push(offset(&points[0]));
+        return Some(polyline);
+    }
+
+    if object.point || object.gid.is_some() || object.width == 0.0 || object.height == 0.0 {
+        return None;
+    }
+
+    let (left, top) = (object.x, object.y);
+    let (right, bottom) = (object.x + object.width, object.y + object.height);
+
+    Some(vec![(left, top), (right, top), (right, bottom), (left, bottom), (left, top)])
+}
+
+// Tiled stores image paths relative to the map file, the asset server wants them relative to the assets folder
+fn asset_path(directory: &Path, image: &str) -> String {
+    let mut path = PathBuf::new();
+
+    for component in directory.join(image).components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::CurDir => {}
+            component => path.push(component),
+        }
+    }
+
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILESET: &str = r#"{"firstgid": 1, "image": "../tiles.png", "columns": 2, "tilecount": 4, "tilewidth": 16, "tileheight": 16}"#;
+    const SPAWN: &str = r#"{"type": "objectgroup", "objects": [{"id": 1, "name": "spawn", "x": 8, "y": 8, "point": true}]}"#;
+
+    // A 4x2 map of 16x16 tiles, 32 pixels high, with the given layers and tilesets
+    fn map(layers: &[&str], tilesets: &[&str]) -> String {
+        format!(
+            r#"{{"width": 4, "height": 2, "tilewidth": 16, "tileheight": 16, "layers": [{}], "tilesets": [{}]}}"#,
+            layers.join(","),
+            tilesets.join(","),
+        )
+    }
+
+    fn load(json: &str) -> Result<Level, bevy::asset::Error> {
+        map_to_level(serde_json::from_str(json).unwrap(), Path::new("levels"))
+    }
+
+    fn level(json: &str) -> Level {
+        match load(json) {
+            Ok(level) => level,
+            Err(error) => panic!("the map should load: {}", error),
+        }
+    }
+
+    fn error(json: &str) -> String {
+        match load(json) {
+            Ok(_) => panic!("the map should be rejected"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn objects_become_spawn_blocks_and_terrain() {
+        let level = level(&map(&[r#"{"type": "objectgroup", "objects": [
+            {"id": 1, "name": "spawn", "x": 8, "y": 8, "point": true},
+            {"id": 2, "name": "block", "x": 16, "y": 0, "width": 16, "height": 16,
+                "properties": [{"name": "contents", "type": "string", "value": "mushroom"}]},
+            {"id": 3, "name": "block", "x": 32, "y": 0, "width": 16, "height": 16},
+            {"id": 4, "name": "", "x": 0, "y": 16, "width": 64, "height": 16}
+        ]}"#], &[]));
+
+        assert_eq!(level.size, (64.0, 32.0));
+        assert_eq!(level.spawn, (8.0, 24.0));
+
+        assert_eq!(level.blocks.len(), 2);
+        assert_eq!(level.blocks[0].position, (24.0, 24.0));
+        assert!(level.blocks[0].contents == BlockContents::Mushroom);
+        assert_eq!(level.blocks[1].position, (40.0, 24.0));
+        assert!(level.blocks[1].contents == BlockContents::Coin);
+
+        assert_eq!(level.terrain, vec![vec![(0.0, 16.0), (64.0, 16.0), (64.0, 0.0), (0.0, 0.0), (0.0, 16.0)]]);
+    }
+
+    #[test]
+    fn one_way_objects_become_platforms() {
+        let level = level(&map(&[SPAWN, r#"{"type": "objectgroup", "objects": [
+            {"id": 2, "name": "", "type": "one_way", "x": 0, "y": 16, "polyline": [{"x": 0, "y": 0}, {"x": 32, "y": 0}]},
+            {"id": 3, "name": "", "x": 32, "y": 8, "polyline": [{"x": 0, "y": 0}, {"x": 32, "y": 0}],
+                "properties": [{"name": "one_way", "type": "bool", "value": true}]},
+            {"id": 4, "name": "", "x": 0, "y": 24, "polyline": [{"x": 0, "y": 0}, {"x": 64, "y": 0}]}
+        ]}"#], &[]));
+
+        assert_eq!(level.platforms, vec![vec![(0.0, 16.0), (32.0, 16.0)], vec![(32.0, 24.0), (64.0, 24.0)]]);
+        assert_eq!(level.terrain, vec![vec![(0.0, 8.0), (64.0, 8.0)]]);
+    }
+
+    #[test]
+    fn coin_and_hidden_block_objects_are_spawned() {
+        let level = level(&map(&[SPAWN, r#"{"type": "objectgroup", "objects": [
+            {"id": 2, "name": "coin", "x": 0, "y": 0, "width": 16, "height": 16},
+            {"id": 3, "name": "hidden_block", "x": 16, "y": 0, "width": 16, "height": 16, "properties": [
+                {"name": "contents", "type": "string", "value": "multi_coin"},
+                {"name": "coins", "type": "int", "value": 3}
+            ]}
+        ]}"#], &[]));
+
+        assert_eq!(level.coins, vec![(8.0, 24.0)]);
+
+        assert_eq!(level.blocks.len(), 1);
+        assert_eq!(level.blocks[0].position, (24.0, 24.0));
+        assert!(level.blocks[0].kind == BlockKind::Hidden);
+        assert!(level.blocks[0].contents == BlockContents::MultiCoin(3));
+    }
+
+    #[test]
+    fn enemy_objects_are_spawned_from_their_center() {
+        let level = level(&map(&[SPAWN, r#"{"type": "objectgroup", "objects": [
+            {"id": 2, "name": "goomba", "x": 32, "y": 0, "width": 16, "height": 16},
+            {"id": 3, "name": "koopa", "x": 48, "y": 0, "width": 16, "height": 24}
+        ]}"#], &[]));
+
+        assert_eq!(level.enemies.len(), 2);
+        assert!(level.enemies[0].kind == EnemyKind::Goomba);
+        assert_eq!(level.enemies[0].position, (40.0, 24.0));
+        assert!(level.enemies[1].kind == EnemyKind::Koopa);
+        assert_eq!(level.enemies[1].position, (56.0, 20.0));
+    }
+
+    #[test]
+    fn tiles_keep_the_order_of_their_layers() {
+        let level = level(&map(&[
+            r#"{"type": "tilelayer", "width": 4, "data": [1, 0, 0, 0, 0, 0, 0, 0]}"#,
+            r#"{"type": "group", "layers": [{"type": "tilelayer", "width": 4, "data": [0, 2, 0, 0, 0, 0, 0, 0]}]}"#,
+            r#"{"type": "imagelayer", "image": "../world.png"}"#,
+            r#"{"type": "tilelayer", "width": 4, "data": [0, 0, 0, 0, 0, 0, 0, 4]}"#,
+            SPAWN,
+        ], &[TILESET]));
+
+        let tiles: Vec<(usize, usize)> = level.tiles.iter().map(|tile| (tile.layer, tile.index)).collect();
+        assert_eq!(tiles, vec![(0, 0), (1, 1), (2, 3)]);
+        assert_eq!(level.tiles[2].position, (56.0, 8.0));
+        assert_eq!(level.background.as_deref(), Some("world.png"));
+        assert_eq!(level.tileset.map(|tileset| (tileset.image, tileset.columns, tileset.rows)), Some(("tiles.png".to_string(), 2, 2)));
+    }
+
+    #[test]
+    fn rejects_a_map_without_spawn() {
+        assert!(error(&map(&[], &[])).contains("spawn"));
+    }
+
+    #[test]
+    fn rejects_an_external_tileset() {
+        let tileset = r#"{"firstgid": 1, "source": "tiles.tsx"}"#;
+        assert!(error(&map(&[SPAWN], &[tileset])).contains("external tileset"));
+    }
+
+    #[test]
+    fn rejects_a_collection_of_images() {
+        let tileset = r#"{"firstgid": 1, "columns": 0, "tilecount": 2}"#;
+        assert!(error(&map(&[SPAWN], &[tileset])).contains("collection of images"));
+    }
+
+    #[test]
+    fn rejects_a_tileset_without_columns() {
+        let tileset = r#"{"firstgid": 1, "image": "../tiles.png", "tilecount": 4, "tilewidth": 16, "tileheight": 16}"#;
+        assert!(error(&map(&[SPAWN], &[tileset])).contains("no columns"));
+    }
+
+    #[test]
+    fn rejects_several_tilesets() {
+        let second = r#"{"firstgid": 5, "image": "../other.png", "columns": 2, "tilecount": 4, "tilewidth": 16, "tileheight": 16}"#;
+        assert!(error(&map(&[SPAWN], &[TILESET, second])).contains("more than one tileset"));
+    }
+
+    #[test]
+    fn rejects_a_tile_outside_of_the_tileset() {
+        let layer = r#"{"type": "tilelayer", "width": 4, "data": [5, 0, 0, 0, 0, 0, 0, 0]}"#;
+        assert!(error(&map(&[layer, SPAWN], &[TILESET])).contains("not part of the tileset"));
+        assert!(error(&map(&[layer, SPAWN], &[])).contains("not part of the tileset"));
+    }
+
+    #[test]
+    fn rejects_an_encoded_tile_layer() {
+        let layer = r#"{"type": "tilelayer", "width": 4, "encoding": "base64", "data": "AQAAAA=="}"#;
+        assert!(error(&map(&[layer, SPAWN], &[TILESET])).contains("encoded"));
+    }
+
+    #[test]
+    fn rejects_an_infinite_map() {
+        let json = map(&[SPAWN], &[TILESET]).replacen('{', r#"{"infinite": true, "#, 1);
+        assert!(error(&json).contains("infinite"));
+    }
+
+    #[test]
+    fn rejects_a_chunked_tile_layer() {
+        let layer = r#"{"type": "tilelayer", "width": 4, "chunks": [{"x": 0, "y": 0, "width": 4, "height": 2, "data": [1, 0, 0, 0, 0, 0, 0, 0]}]}"#;
+        assert!(error(&map(&[layer, SPAWN], &[TILESET])).contains("chunked"));
+    }
+
+    #[test]
+    fn rejects_a_layer_offset() {
+        let layer = r#"{"type": "objectgroup", "offsetx": 4, "objects": []}"#;
+        assert!(error(&map(&[layer, SPAWN], &[])).contains("offsets"));
+
+        let group = r#"{"type": "group", "offsety": -2, "layers": []}"#;
+        assert!(error(&map(&[group, SPAWN], &[])).contains("offsets"));
+    }
+
+    #[test]
+    fn rejects_a_rotated_object() {
+        let layer = r#"{"type": "objectgroup", "objects": [{"id": 2, "name": "", "x": 0, "y": 16, "width": 64, "height": 16, "rotation": 90}]}"#;
+        assert!(error(&map(&[layer, SPAWN], &[])).contains("object 2 is rotated"));
+    }
+
+    #[test]
+    fn rejects_unknown_block_contents() {
+        let layer = r#"{"type": "objectgroup", "objects": [{"id": 2, "name": "block", "x": 16, "y": 0, "width": 16, "height": 16,
+            "properties": [{"name": "contents", "type": "string", "value": "goomba"}]}]}"#;
+        assert!(error(&map(&[layer, SPAWN], &[])).contains("unknown contents goomba"));
+    }
+}
@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+
+// Speeds are in pixels per second, accelerations in pixels per second squared and times in seconds
+#[derive(Resource)]
+pub struct MovementTuning {
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    // deceleration applied while pushing against the current direction
+    pub skid_deceleration: f32,
+    // deceleration applied while sliding on the ground crouched
+    pub crouch_deceleration: f32,
+    pub gravity: f32,
+    // gravity is scaled down while a held jump is close to its apex, so that it hangs there a little
+    pub apex_gravity_scale: f32,
+    pub apex_threshold: f32,
+    pub terminal_velocity: f32,
+    pub jump_height: f32,
+    // reached even when the jump action is only tapped
+    pub min_jump_height: f32,
+    // extra height when jumping at full run speed
+    pub run_jump_bonus: f32,
+    // share of the upward velocity kept when the jump action is released early
+    pub jump_cut: f32,
+    pub coyote_time: f32,
+    pub jump_buffer_time: f32,
+}
+
+impl Default for MovementTuning {
+    fn default() -> Self {
+        Self {
+            walk_speed: 400.0,
+            run_speed: 600.0,
+            acceleration: 1600.0,
+            deceleration: 1800.0,
+            skid_deceleration: 3200.0,
+            crouch_deceleration: 700.0,
+            gravity: 3200.0,
+            apex_gravity_scale: 0.5,
+            apex_threshold: 120.0,
+            terminal_velocity: 900.0,
+            jump_height: 230.0,
+            min_jump_height: 60.0,
+            run_jump_bonus: 60.0,
+            jump_cut: 0.4,
+            coyote_time: 0.1,
+            jump_buffer_time: 0.12,
+        }
+    }
+}
+
+impl MovementTuning {
+    // Initial upward velocity needed for a held jump to peak at the given height, apex hang included
+    pub fn jump_velocity(&self, height: f32) -> f32 {
+        let threshold = self.apex_threshold * self.apex_threshold;
+        let velocity = 2.0 * self.gravity * height + threshold - threshold / self.apex_gravity_scale;
+
+        if velocity < threshold {
+            // the whole jump happens in the apex hang
+            return (2.0 * self.gravity * self.apex_gravity_scale * height).sqrt();
+        }
+
+        velocity.sqrt()
+    }
+}
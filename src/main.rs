@@ -3,15 +3,29 @@
 mod utils;
 
 mod game_logic {
+    pub mod input;
+    pub mod lives;
+
     pub mod entities {
         pub mod objects;
         pub mod champi;
         pub mod mario;
         pub mod block;
+        pub mod goal;
+        pub mod checkpoint;
+        pub mod enemy;
+        pub mod fireball;
+        pub mod coin;
+        pub mod emerging;
     }
 
     pub mod world {
         pub mod physics;
+        pub mod level;
+        pub mod tiled;
+        pub mod platform;
+        pub mod kill_zone;
+        pub mod tuning;
     }
 }
 
@@ -19,6 +33,7 @@ mod rendering {
     pub mod camera;
     pub mod sprite_manager;
     pub mod animation;
+    pub mod hud;
 }
 
 use std::ops::Deref;
@@ -34,7 +49,10 @@ use bevy_rapier2d::parry::partitioning::IndexedData;
 use bevy_rapier2d::prelude::*;
 use bevy_rapier2d::rapier::pipeline::PhysicsHooks;
 use crate::game_logic::entities::objects::ObjectsPlugin;
-use crate::game_logic::world::physics::world_to_vec;
+use crate::game_logic::input::ActionsPlugin;
+use crate::game_logic::lives::LivesPlugin;
+use crate::rendering::hud::HudPlugin;
+use crate::game_logic::world::level::LevelPlugin;
 
 const WINDOW_WIDTH: f32 = 1024.0;
 const WINDOW_HEIGHT: f32 = 720.0;
@@ -44,9 +62,6 @@ const WINDOW_LEFT_X: f32 = -WINDOW_WIDTH / 2.0;
 
 const COLOR_BACKGROUND: Color = Color::rgb(0.29, 0.31, 0.41);
 
-const BG_WIDTH: f32 = 5120.0;
-const BG_HEIGHT: f32 = 432.0;
-
 const SCALE: f32 = 2.0;
 
 fn main() {
@@ -64,33 +79,18 @@ fn main() {
                 ..Default::default()
             }),
             RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(200.0),
+            ActionsPlugin,
             AnimationPlugin,
             SpriteManagerPlugin,
+            LevelPlugin,
             PhysicsPlugin,
+            LivesPlugin,
+            HudPlugin,
             CameraPlugin,
         ))
         .run();
 }
 
 fn setup(mut commands: Commands) {
-    let (vertices, indices) =
-        world_to_vec();
-
-    commands
-        .spawn(SpriteBundle {
-            transform: Transform {
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(RigidBody::Fixed)
-        .insert(Collider::polyline(vertices, Option::from(indices)))
-        .insert(ActiveEvents::COLLISION_EVENTS)
-        .insert(CollisionGroups::new(
-            Group::GROUP_1,
-            Group::ALL
-        ))
-    ;
-
     commands.spawn(Camera2dBundle::default());
 }
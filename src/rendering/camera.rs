@@ -1,19 +1,59 @@
 pub struct CameraPlugin;
 use bevy::prelude::*;
-use bevy_rapier2d::control::KinematicCharacterControllerOutput;
+use crate::game_logic::entities::mario::{Dead, movement, Player};
+use crate::game_logic::world::physics::{apply_kinematic_velocity, KinematicVelocity};
+use crate::{WINDOW_LEFT_X, WINDOW_WIDTH};
+
+// Horizontal position of the players on screen, taken from the middle of them
+const PLAYER_SCREEN_X: f32 = WINDOW_LEFT_X + 300.0;
+const FRAME_MARGIN: f32 = 32.0;
+// The left edge is the closest one to the players, so it decides how far apart they can walk
+const MAX_PLAYER_SPREAD: f32 = 2.0 * (WINDOW_WIDTH / 2.0 + PLAYER_SCREEN_X - FRAME_MARGIN);
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, sync_player_camera);
+        app.add_systems(
+            Update,
+            (
+                sync_player_camera,
+                keep_players_in_frame.after(movement).before(apply_kinematic_velocity),
+            ),
+        );
     }
 }
 
 pub fn sync_player_camera(
-    mut player: Query<&mut KinematicCharacterControllerOutput>,
-    mut camera: Query<(&mut Camera, &mut Transform)>,
+    players: Query<&Transform, (With<Player>, Without<Dead>)>,
+    mut camera: Query<&mut Transform, (With<Camera>, Without<Player>)>,
+) {
+    if players.is_empty() {
+        return;
+    }
+    let Ok(mut camera_transform) = camera.get_single_mut() else { return };
+
+    let count = players.iter().count() as f32;
+    let middle = players.iter().map(|transform| transform.translation.x).sum::<f32>() / count;
+
+    camera_transform.translation.x = middle - PLAYER_SCREEN_X;
+}
+
+// Outermost players can't walk away from each other once the camera can't fit them both
+pub fn keep_players_in_frame(
+    mut query: Query<(&Transform, &mut KinematicVelocity), (With<Player>, Without<Dead>)>,
 ) {
-    let Ok(player) = player.get_single() else { return };
-    let Ok((mut camera, mut camera_transform)) = camera.get_single_mut() else { return };
+    let Some(left) = query.iter().map(|(transform, _)| transform.translation.x).reduce(f32::min) else { return };
+    let Some(right) = query.iter().map(|(transform, _)| transform.translation.x).reduce(f32::max) else { return };
 
-    camera_transform.translation.x += player.effective_translation.x;
+    if right - left < MAX_PLAYER_SPREAD {
+        return;
+    }
+
+    for (transform, mut velocity) in query.iter_mut() {
+        if transform.translation.x <= left && velocity.0.x < 0.0 {
+            velocity.0.x = 0.0;
+        }
+        if transform.translation.x >= right && velocity.0.x > 0.0 {
+            velocity.0.x = 0.0;
+        }
+    }
 }
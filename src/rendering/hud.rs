@@ -0,0 +1,131 @@
+use bevy::prelude::*;
+use crate::game_logic::entities::coin::Coins;
+use crate::game_logic::entities::mario::{Character, Player, Score};
+use crate::game_logic::lives::{GameState, Lives};
+
+const HUD_FONT_SIZE: f32 = 32.0;
+const GAME_OVER_FONT_SIZE: f32 = 64.0;
+const HUD_MARGIN: f32 = 16.0;
+const HUD_LINE_HEIGHT: f32 = 40.0;
+const HUD_PLAYERS: usize = 2;
+const COLOR_HUD: Color = Color::WHITE;
+const COLOR_GAME_OVER_BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.8);
+
+// Lives and score of the player with the same index
+#[derive(Component)]
+struct PlayerText {
+    index: usize,
+}
+
+// Shared by every player, below their own lines
+#[derive(Component)]
+struct CoinsText;
+
+#[derive(Component)]
+struct GameOverScreen;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, setup)
+            .add_systems(Update, (update_player_text, update_coins_text))
+            .add_systems(OnEnter(GameState::GameOver), show_game_over_screen)
+            .add_systems(OnExit(GameState::GameOver), hide_game_over_screen);
+    }
+}
+
+fn setup(mut commands: Commands) {
+    for index in 0..HUD_PLAYERS {
+        commands
+            .spawn(
+                TextBundle::from_section("", TextStyle {
+                    font_size: HUD_FONT_SIZE,
+                    color: COLOR_HUD,
+                    ..Default::default()
+                })
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(HUD_MARGIN + index as f32 * HUD_LINE_HEIGHT),
+                    left: Val::Px(HUD_MARGIN),
+                    ..Default::default()
+                }),
+            )
+            .insert(PlayerText { index });
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section("", TextStyle {
+                font_size: HUD_FONT_SIZE,
+                color: COLOR_HUD,
+                ..Default::default()
+            })
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(HUD_MARGIN + HUD_PLAYERS as f32 * HUD_LINE_HEIGHT),
+                left: Val::Px(HUD_MARGIN),
+                ..Default::default()
+            }),
+        )
+        .insert(CoinsText);
+}
+
+fn update_coins_text(coins: Res<Coins>, mut query: Query<&mut Text, With<CoinsText>>) {
+    if !coins.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("COINS x{:02}", coins.0);
+    }
+}
+
+fn update_player_text(
+    players: Query<(&Player, &Character, &Lives, &Score), Or<(Changed<Lives>, Changed<Score>)>>,
+    mut query: Query<(&PlayerText, &mut Text)>,
+) {
+    for (player, character, lives, score) in players.iter() {
+        for (player_text, mut text) in query.iter_mut() {
+            if player_text.index == player.index {
+                text.sections[0].value = format!("{} x{}  {:06}", character.name(), lives.0, score.0);
+            }
+        }
+    }
+}
+
+fn show_game_over_screen(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: COLOR_GAME_OVER_BACKGROUND.into(),
+            ..Default::default()
+        })
+        .insert(GameOverScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("GAME OVER", TextStyle {
+                font_size: GAME_OVER_FONT_SIZE,
+                color: COLOR_HUD,
+                ..Default::default()
+            }));
+            parent.spawn(TextBundle::from_section("Press jump to continue", TextStyle {
+                font_size: HUD_FONT_SIZE,
+                color: COLOR_HUD,
+                ..Default::default()
+            }));
+        });
+}
+
+fn hide_game_over_screen(mut commands: Commands, query: Query<Entity, With<GameOverScreen>>) {
+    for screen in query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
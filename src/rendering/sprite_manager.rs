@@ -1,28 +1,132 @@
 use std::time::Duration;
 use crate::rendering::animation::Animation;
-use crate::{WINDOW_BOTTOM_Y, WINDOW_LEFT_X, SCALE, BG_WIDTH, BG_HEIGHT};
+use crate::{WINDOW_BOTTOM_Y, WINDOW_LEFT_X, SCALE};
 use bevy::prelude::*;
 use bevy::prelude::Visibility::{Hidden, Visible};
+use bevy::sprite::Anchor;
 use bevy_rapier2d::prelude::*;
-use crate::game_logic::entities::block::{Block, BlockFactory, Opened};
-use crate::game_logic::entities::champi::{Champi, ChampiFactory};
-use crate::game_logic::entities::mario::{COLLISION_GROUPS_DEFAULT, Direction, Mario};
+use crate::game_logic::entities::block::{Block, BlockFactory, Opened, Revealed};
+use crate::game_logic::entities::checkpoint::CheckpointFactory;
+use crate::game_logic::entities::coin::CoinFactory;
+use crate::game_logic::entities::enemy::{GoombaFactory, KnockedOut, Koopa, KoopaFactory, Shell, Squashed};
+use crate::game_logic::entities::mario::{Character, Dead, Direction, Invincible, Mario, Player, PlayerState, PowerUp};
+use crate::game_logic::entities::goal::GoalFactory;
+use crate::game_logic::input::{JoinedPlayers, PlayerJoined};
+use crate::game_logic::world::level::{CurrentLevel, EnemyKind, Level, LevelEntity, LevelLoaded};
+use crate::utils::build_point;
 
 pub struct SpriteManagerPlugin;
 
+pub struct CharacterAtlases {
+    pub small: Handle<TextureAtlas>,
+    pub big: Handle<TextureAtlas>,
+}
+
+#[derive(Resource)]
+pub struct PlayerAtlases {
+    pub mario: CharacterAtlases,
+    pub luigi: CharacterAtlases,
+}
+
+impl PlayerAtlases {
+    pub fn get(&self, character: Character) -> &CharacterAtlases {
+        match character {
+            Character::Mario => &self.mario,
+            Character::Luigi => &self.luigi,
+        }
+    }
+}
+
+// Luigi is drawn from the Mario spritesheet, painted with his own palette once it is loaded
+#[derive(Resource)]
+struct LuigiPalette {
+    source: Handle<Image>,
+    target: Handle<Image>,
+    painted: bool,
+}
+
+const LUIGI_PALETTE: &[([u8; 3], [u8; 3])] = &[
+    ([248, 64, 112], [88, 216, 64]),
+    ([176, 40, 96], [32, 136, 48]),
+];
+
+// Players in the order of the input bindings
+const PLAYERS: &[Character] = &[Character::Mario, Character::Luigi];
+const PLAYER_SPACING: f32 = 40.0;
+
+// Items that are not in the block tileset (see assets/spritesheets/items.png), and the block tileset with the mushroom
+#[derive(Resource)]
+pub struct ItemAtlases {
+    pub items: Handle<TextureAtlas>,
+    pub blocks: Handle<TextureAtlas>,
+}
+
+pub const SPRITE_IDX_FIRE_FLOWER_ANIM: &[usize] = &[0, 1];
+pub const SPRITE_IDX_FIREBALL_ANIM: &[usize] = &[2, 3, 4, 5];
+pub const SPRITE_IDX_STAR_ANIM: &[usize] = &[6, 7, 8, 9];
+pub const SPRITE_IDX_COIN_ANIM: &[usize] = &[10, 11, 12, 11];
+pub const SPRITE_IDX_ONE_UP: usize = 13;
+pub const SPRITE_IDX_BRICK: usize = 14;
+pub const SPRITE_IDX_DEBRIS: usize = 15;
+pub const ITEM_CYCLE_DELAY: Duration = Duration::from_millis(150);
+const SPRITE_ITEM_SIZE: f32 = 16.0;
+const SPRITE_FIREBALL_SIZE: f32 = 8.0;
+
+// See assets/spritesheets/enemies.png, Goombas on the first row and Koopas on the second one
+#[derive(Resource)]
+pub struct EnemyAtlases {
+    pub enemies: Handle<TextureAtlas>,
+}
+
+pub const SPRITE_IDX_GOOMBA_WALK_ANIM: &[usize] = &[0, 1];
+const SPRITE_IDX_GOOMBA_SQUASHED: usize = 2;
+pub const SPRITE_IDX_KOOPA_WALK_ANIM: &[usize] = &[3, 4];
+const SPRITE_IDX_SHELL: usize = 5;
+const SPRITE_ENEMY_SIZE: f32 = 16.0;
+const SPRITE_KOOPA_HEIGHT: f32 = 24.0;
+
 pub const SPRITESHEET_COLS: usize = 5;
 pub const SPRITESHEET_ROWS: usize = 2;
 const SPRITE_MARIO_WIDTH: f32 = 13.0;
 const SPRITE_MARIO_HEIGHT: f32 = 23.0;
+const SPRITE_BIG_MARIO_HEIGHT: f32 = 30.0;
+
+// There is no fire Mario in the spritesheet, the big one is tinted instead
+const FIRE_TINT: Color = Color::rgb(1.0, 0.8, 0.55);
+const BLINK_DELAY: Duration = Duration::from_millis(80);
 
 pub const SPRITE_OFFSET_X: f32 = 25.0;
 pub const SPRITE_OFFSET_Y: f32 = 49.0;
 pub const SPRITE_PADDING_X: f32 = 39.0;
 pub const SPRITE_PADDING_Y: f32 = 40.0;
+const SPRITE_BIG_OFFSET_Y: f32 = 584.0;
 
 const SPRITE_IDX_STAND: usize = 0;
+const SPRITE_IDX_CROUCH: usize = 2;
 const SPRITE_IDX_JUMP: usize = 6;
-const SPRITE_IDX_BLOCK_OPENED: usize = 4;
+const SPRITE_IDX_FALL: usize = 7;
+const SPRITE_IDX_WALKING: &[usize] = &[0, 4, 3];
+const SPRITE_IDX_GROWING: &[usize] = &[0, 2];
+const SPRITE_IDX_DEAD: usize = SPRITESHEET_COLS * SPRITESHEET_ROWS; // added after the grid in build_mario_atlas
+const SPRITE_IDX_SKID: usize = SPRITE_IDX_DEAD + 1;
+
+// The crouch collider is half as tall as the frame and shares its bottom, the frame is moved up to match
+const CROUCH_ANCHOR: Vec2 = Vec2::new(0.0, -0.25);
+
+const WALK_CYCLE_DELAY: Duration = Duration::from_millis(70);
+const RUN_CYCLE_DELAY: Duration = Duration::from_millis(40);
+const GROW_CYCLE_DELAY: Duration = Duration::from_millis(100);
+
+// The death and skid frames are not aligned with the grid of the other frames
+const SPRITE_DEAD_X: f32 = 545.0;
+const SPRITE_DEAD_Y: f32 = 267.0;
+const SPRITE_SKID_X: f32 = 701.0;
+pub const SPRITE_IDX_BLOCK_OPENED: usize = 4;
+
+// Behind every entity, which are drawn from z = 0, the tiles of a level are drawn over its background
+const WORLD_IMAGE_Z: f32 = -0.2;
+const TILE_Z: f32 = -0.1;
+const TILE_LAYER_Z_STEP: f32 = 0.001;
 
 pub const SPRITE_TILE_WIDTH: f32 = 15.0;
 pub const SPRITE_TILE_HEIGHT: f32 = 15.0;
@@ -32,19 +136,29 @@ const SPRITE_TILE_PADDING_Y: f32 = 6.0;
 impl Plugin for SpriteManagerPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(Startup, (
-                setup,
-                add_world_image,
-                add_block_to_world,
-                add_champi,
-            ))
+            .add_systems(Startup, setup)
             .add_systems(
             Update,
             (
-                apply_jump_sprite,
-                apply_idle_sprite,
+                paint_luigi_palette,
+                spawn_joined_players,
+                move_player_to_spawn,
+                add_world_image,
+                add_tiles,
+                add_block_to_world,
+                add_goal,
+                add_checkpoints,
+                add_coins,
+                add_enemies,
+                apply_player_state_sprite,
+                apply_power_up_atlas,
+                blink_invincible_player,
                 update_sprite_direction,
-                apply_opened_block_sprite
+                apply_opened_block_sprite,
+                reveal_hidden_blocks,
+                apply_squashed_sprite,
+                apply_shell_sprite,
+                apply_knocked_out_sprite
             ),
         );
     }
@@ -52,70 +166,285 @@ impl Plugin for SpriteManagerPlugin {
 
 fn setup(
     mut commands: Commands,
+    joined: Res<JoinedPlayers>,
     mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut images: ResMut<Assets<Image>>,
     server: Res<AssetServer>,
 ) {
     let image_handle: Handle<Image> = server.load("spritesheets/spritesheet_Mario.png");
-    let texture_atlas = TextureAtlas::from_grid(
-        image_handle,
-        Vec2::new(SPRITE_MARIO_WIDTH, SPRITE_MARIO_HEIGHT),
-        SPRITESHEET_COLS,
-        SPRITESHEET_ROWS,
-        Option::from(Vec2::new(SPRITE_PADDING_X, SPRITE_PADDING_Y)),
-        Option::from(Vec2::new(SPRITE_OFFSET_X, SPRITE_OFFSET_Y)),
+    let luigi_image_handle = images.add(Image::default());
+
+    let player_atlases = PlayerAtlases {
+        mario: build_character_atlases(&mut atlases, image_handle.clone()),
+        luigi: build_character_atlases(&mut atlases, luigi_image_handle.clone()),
+    };
+
+    commands.insert_resource(LuigiPalette {
+        source: image_handle,
+        target: luigi_image_handle,
+        painted: false,
+    });
+
+    let mut item_atlas = TextureAtlas::new_empty(
+        server.load("spritesheets/items.png"),
+        Vec2::new(SPRITE_ITEM_SIZE * 6.0, SPRITE_ITEM_SIZE * 3.0),
     );
-    let atlas_handle = atlases.add(texture_atlas);
+    for i in 0..SPRITE_IDX_FIRE_FLOWER_ANIM.len() {
+        let x = i as f32 * SPRITE_ITEM_SIZE;
+        item_atlas.add_texture(Rect::new(x, 0.0, x + SPRITE_ITEM_SIZE, SPRITE_ITEM_SIZE));
+    }
+    for i in 0..SPRITE_IDX_FIREBALL_ANIM.len() {
+        let x = i as f32 * SPRITE_FIREBALL_SIZE;
+        item_atlas.add_texture(Rect::new(
+            x,
+            SPRITE_ITEM_SIZE,
+            x + SPRITE_FIREBALL_SIZE,
+            SPRITE_ITEM_SIZE + SPRITE_FIREBALL_SIZE,
+        ));
+    }
+    // stars on the first row, coins and the 1-up on the second one, right of the flower and the fireballs
+    for i in 0..SPRITE_IDX_STAR_ANIM.len() {
+        let x = (i + 2) as f32 * SPRITE_ITEM_SIZE;
+        item_atlas.add_texture(Rect::new(x, 0.0, x + SPRITE_ITEM_SIZE, SPRITE_ITEM_SIZE));
+    }
+    for i in 0..=SPRITE_IDX_ONE_UP - SPRITE_IDX_COIN_ANIM[0] {
+        let x = (i + 2) as f32 * SPRITE_ITEM_SIZE;
+        item_atlas.add_texture(Rect::new(x, SPRITE_ITEM_SIZE, x + SPRITE_ITEM_SIZE, SPRITE_ITEM_SIZE * 2.0));
+    }
+    // the brick and one of its debris on the third row
+    item_atlas.add_texture(Rect::new(0.0, SPRITE_ITEM_SIZE * 2.0, SPRITE_ITEM_SIZE, SPRITE_ITEM_SIZE * 3.0));
+    item_atlas.add_texture(Rect::new(
+        SPRITE_ITEM_SIZE,
+        SPRITE_ITEM_SIZE * 2.0,
+        SPRITE_ITEM_SIZE + SPRITE_FIREBALL_SIZE,
+        SPRITE_ITEM_SIZE * 2.0 + SPRITE_FIREBALL_SIZE,
+    ));
+
+    let block_atlas = TextureAtlas::from_grid(
+        server.load("spritesheets/tiles.png"),
+        Vec2::new(SPRITE_TILE_WIDTH, SPRITE_TILE_HEIGHT),
+        4,
+        2,
+        Option::from(Vec2::new(SPRITE_TILE_PADDING, SPRITE_TILE_PADDING_Y)),
+        Option::from(Vec2::new(8.0, 248.0)),
+    );
+
+    commands.insert_resource(ItemAtlases {
+        items: atlases.add(item_atlas),
+        blocks: atlases.add(block_atlas),
+    });
+
+    let mut enemy_atlas = TextureAtlas::new_empty(
+        server.load("spritesheets/enemies.png"),
+        Vec2::new(SPRITE_ENEMY_SIZE * 3.0, SPRITE_ENEMY_SIZE + SPRITE_KOOPA_HEIGHT),
+    );
+    for i in 0..=SPRITE_IDX_GOOMBA_SQUASHED {
+        let x = i as f32 * SPRITE_ENEMY_SIZE;
+        enemy_atlas.add_texture(Rect::new(x, 0.0, x + SPRITE_ENEMY_SIZE, SPRITE_ENEMY_SIZE));
+    }
+    for i in 0..SPRITE_IDX_KOOPA_WALK_ANIM.len() {
+        let x = i as f32 * SPRITE_ENEMY_SIZE;
+        enemy_atlas.add_texture(Rect::new(x, SPRITE_ENEMY_SIZE, x + SPRITE_ENEMY_SIZE, SPRITE_ENEMY_SIZE + SPRITE_KOOPA_HEIGHT));
+    }
+    // the shell is as big as a Goomba, at the bottom of its row
+    let shell_x = SPRITE_ENEMY_SIZE * 2.0;
+    let shell_y = SPRITE_KOOPA_HEIGHT;
+    enemy_atlas.add_texture(Rect::new(shell_x, shell_y, shell_x + SPRITE_ENEMY_SIZE, shell_y + SPRITE_ENEMY_SIZE));
+
+    commands.insert_resource(EnemyAtlases {
+        enemies: atlases.add(enemy_atlas),
+    });
+
+    spawn_players(&mut commands, &player_atlases, joined.0);
+    commands.insert_resource(player_atlases);
+}
+
+// Also used to bring back fresh players when continuing after a game over
+pub fn spawn_players(commands: &mut Commands, player_atlases: &PlayerAtlases, count: usize) {
+    for index in 0..count {
+        let x = WINDOW_LEFT_X + 300.0 + index as f32 * PLAYER_SPACING;
+
+        spawn_player(commands, player_atlases, index, x, WINDOW_BOTTOM_Y + 300.0);
+    }
+}
+
+fn spawn_player(commands: &mut Commands, player_atlases: &PlayerAtlases, index: usize, x: f32, y: f32) {
+    let Some(character) = PLAYERS.get(index).copied() else { return };
 
     commands
-        .spawn(Mario::new(atlas_handle, WINDOW_LEFT_X + 300.0, WINDOW_BOTTOM_Y + 300.0 ))
+        .spawn(Mario::new(index, character, player_atlases.get(character).small.clone(), x, y))
         .insert(KinematicCharacterController {
-            filter_groups: Option::from(COLLISION_GROUPS_DEFAULT),
+            filter_groups: Option::from(Player { index }.collision_groups()),
             ..Default::default()
         });
 }
 
-
-fn apply_jump_sprite(
+// A player joining the game shows up next to the ones already playing
+fn spawn_joined_players(
     mut commands: Commands,
-    mut query: Query<(
-        Entity,
-        &KinematicCharacterControllerOutput,
-        &mut TextureAtlasSprite,
-    )>,
+    player_atlases: Res<PlayerAtlases>,
+    mut joined: ResMut<JoinedPlayers>,
+    mut player_joined: EventReader<PlayerJoined>,
+    partners: Query<&Transform, (With<Player>, Without<Dead>)>,
 ) {
-    if query.is_empty() {
+    for event in player_joined.iter() {
+        if event.index != joined.0 || event.index >= PLAYERS.len() {
+            continue;
+        }
+        let Some(partner) = partners.iter().next() else { continue };
+
+        let x = partner.translation.x + PLAYER_SPACING;
+        spawn_player(&mut commands, &player_atlases, event.index, x, partner.translation.y);
+        joined.0 += 1;
+    }
+}
+
+fn build_character_atlases(atlases: &mut Assets<TextureAtlas>, image_handle: Handle<Image>) -> CharacterAtlases {
+    CharacterAtlases {
+        small: atlases.add(build_mario_atlas(image_handle.clone(), SPRITE_MARIO_HEIGHT, SPRITE_OFFSET_Y)),
+        big: atlases.add(build_mario_atlas(image_handle, SPRITE_BIG_MARIO_HEIGHT, SPRITE_BIG_OFFSET_Y)),
+    }
+}
+
+fn paint_luigi_palette(mut palette: ResMut<LuigiPalette>, mut images: ResMut<Assets<Image>>) {
+    if palette.painted {
         return;
     }
 
-    for (player, output, mut sprite) in query.iter_mut() {
-        if !output.grounded {
-            commands.entity(player).remove::<Animation>();
-            sprite.index = SPRITE_IDX_JUMP
+    let Some(source) = images.get(&palette.source) else { return };
+    let mut image = source.clone();
+
+    for pixel in image.data.chunks_exact_mut(4) {
+        if let Some((_, to)) = LUIGI_PALETTE.iter().find(|(from, _)| pixel[..3] == from[..]) {
+            pixel[..3].copy_from_slice(to);
         }
     }
+
+    images.set_untracked(palette.target.id(), image);
+    palette.painted = true;
 }
 
-fn apply_idle_sprite(
-    mut commands: Commands,
-    mut query: Query<(
-        Entity,
-        &KinematicCharacterControllerOutput,
-        &mut TextureAtlasSprite,
-    )>,
+// Every Mario atlas shares the same indices, whatever the size of the frames
+fn build_mario_atlas(image_handle: Handle<Image>, height: f32, offset_y: f32) -> TextureAtlas {
+    let mut texture_atlas = TextureAtlas::from_grid(
+        image_handle,
+        Vec2::new(SPRITE_MARIO_WIDTH, height),
+        SPRITESHEET_COLS,
+        SPRITESHEET_ROWS,
+        Option::from(Vec2::new(SPRITE_PADDING_X, SPRITE_PADDING_Y)),
+        Option::from(Vec2::new(SPRITE_OFFSET_X, offset_y)),
+    );
+    texture_atlas.add_texture(Rect::new(
+        SPRITE_DEAD_X,
+        SPRITE_DEAD_Y,
+        SPRITE_DEAD_X + SPRITE_MARIO_WIDTH,
+        SPRITE_DEAD_Y + SPRITE_MARIO_HEIGHT,
+    ));
+    texture_atlas.add_texture(Rect::new(
+        SPRITE_SKID_X,
+        offset_y,
+        SPRITE_SKID_X + SPRITE_MARIO_WIDTH,
+        offset_y + height,
+    ));
+
+    texture_atlas
+}
+
+fn move_player_to_spawn(
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut query: Query<(&Player, &mut Transform)>,
 ) {
-    if query.is_empty() {
+    if level_loaded.is_empty() {
         return;
     }
+    level_loaded.clear();
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    let spawn = build_point(level.spawn.0, level.spawn.1);
+
+    for (player, mut transform) in query.iter_mut() {
+        transform.translation.x = spawn.x + player.index as f32 * PLAYER_SPACING;
+        transform.translation.y = spawn.y;
+    }
+}
+
+fn apply_player_state_sprite(
+    mut commands: Commands,
+    mut query: Query<(Entity, &PlayerState, &mut TextureAtlasSprite), Changed<PlayerState>>,
+) {
+    for (player, state, mut sprite) in query.iter_mut() {
+        let animation = match state {
+            PlayerState::Walk => Some(Animation::new(SPRITE_IDX_WALKING, WALK_CYCLE_DELAY)),
+            PlayerState::Run => Some(Animation::new(SPRITE_IDX_WALKING, RUN_CYCLE_DELAY)),
+            PlayerState::Grow => Some(Animation::new(SPRITE_IDX_GROWING, GROW_CYCLE_DELAY)),
+            _ => None,
+        };
+
+        sprite.index = match state {
+            PlayerState::Idle | PlayerState::Walk | PlayerState::Run | PlayerState::Grow => SPRITE_IDX_STAND,
+            PlayerState::Skid => SPRITE_IDX_SKID,
+            PlayerState::Jump => SPRITE_IDX_JUMP,
+            PlayerState::Fall => SPRITE_IDX_FALL,
+            PlayerState::Crouch => SPRITE_IDX_CROUCH,
+            PlayerState::Dead => SPRITE_IDX_DEAD,
+        };
+
+        sprite.anchor = match state {
+            PlayerState::Crouch => Anchor::Custom(CROUCH_ANCHOR),
+            _ => Anchor::Center,
+        };
+
+        match animation {
+            Some(animation) => commands.entity(player).insert(animation),
+            None => commands.entity(player).remove::<Animation>(),
+        };
+    }
+}
+
+fn apply_power_up_atlas(
+    player_atlases: Res<PlayerAtlases>,
+    mut query: Query<(&PowerUp, &Character, &mut Handle<TextureAtlas>, &mut TextureAtlasSprite), Changed<PowerUp>>,
+) {
+    for (power_up, character, mut texture_atlas, mut sprite) in query.iter_mut() {
+        let character_atlases = player_atlases.get(*character);
+
+        *texture_atlas = match power_up {
+            PowerUp::Small => character_atlases.small.clone(),
+            PowerUp::Big | PowerUp::Fire => character_atlases.big.clone(),
+        };
+
+        sprite.color = match power_up {
+            PowerUp::Fire => FIRE_TINT,
+            _ => Color::WHITE,
+        };
+    }
+}
 
-    for (player, output, mut sprite) in query.iter_mut() {
-        if output.desired_translation.x == 0.0 && output.grounded {
-            commands.entity(player).remove::<Animation>();
-            sprite.index = SPRITE_IDX_STAND
+fn blink_invincible_player(
+    mut query: Query<(&Invincible, &mut Visibility)>,
+    mut removed: RemovedComponents<Invincible>,
+    mut visibilities: Query<&mut Visibility, Without<Invincible>>,
+) {
+    for (invincible, mut visibility) in query.iter_mut() {
+        let blinks = invincible.0.elapsed().as_millis() / BLINK_DELAY.as_millis();
+
+        *visibility = if blinks % 2 == 0 {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+
+    for player in removed.iter() {
+        if let Ok(mut visibility) = visibilities.get_mut(player) {
+            *visibility = Visibility::Inherited;
         }
     }
 }
 
-fn update_sprite_direction(mut query: Query<(&mut TextureAtlasSprite, &Direction)>) {
+fn update_sprite_direction(mut query: Query<(&mut TextureAtlasSprite, &Direction), Or<(With<Player>, With<Koopa>)>>) {
     if query.is_empty() {
         return;
     }
@@ -129,51 +458,112 @@ fn update_sprite_direction(mut query: Query<(&mut TextureAtlasSprite, &Direction
     }
 }
 
-fn add_world_image(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let world_image = asset_server.load("textures/world.png");
+fn add_world_image(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    let Some(background) = &level.background else { return };
+    let world_image = asset_server.load(background.as_str());
+    let center = build_point(level.size.0 / 2.0, level.size.1 / 2.0);
 
-    commands.spawn(SpriteBundle {
-        texture: world_image,
-        transform: Transform {
-            scale: Vec3::new(SCALE, SCALE, 1.0),
-            translation: Vec3::new(BG_WIDTH + WINDOW_LEFT_X, WINDOW_BOTTOM_Y + BG_HEIGHT, 0.0),
+    commands
+        .spawn(SpriteBundle {
+            texture: world_image,
+            transform: Transform {
+                scale: Vec3::new(SCALE, SCALE, 1.0),
+                translation: Vec3::new(center.x, center.y, WORLD_IMAGE_Z),
+                ..Default::default()
+            },
             ..Default::default()
-        },
-        ..Default::default()
-    });
+        })
+        .insert(LevelEntity);
 }
 
-fn add_block_to_world(
+fn add_tiles(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
 ) {
-    let image_handle: Handle<Image> = asset_server.load("spritesheets/tiles.png");
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    let Some(tileset) = &level.tileset else { return };
+
+    let image_handle: Handle<Image> = asset_server.load(tileset.image.as_str());
     let texture_atlas = TextureAtlas::from_grid(
         image_handle,
-        Vec2::new(SPRITE_TILE_WIDTH, SPRITE_TILE_HEIGHT),
-        4,
-        2,
-        Option::from(Vec2::new(SPRITE_TILE_PADDING, SPRITE_TILE_PADDING_Y)),
-        Option::from(Vec2::new(8.0, 248.0)),
+        Vec2::new(tileset.tile_size.0, tileset.tile_size.1),
+        tileset.columns,
+        tileset.rows,
+        Option::from(Vec2::new(tileset.spacing, tileset.spacing)),
+        Option::from(Vec2::new(tileset.margin, tileset.margin)),
     );
     let atlas_handle = atlases.add(texture_atlas);
 
-    let atlas_handle1 = atlas_handle.clone();
-    let atlas_handle2 = atlas_handle.clone();
-    let atlas_handle3 = atlas_handle.clone();
+    for tile in level.tiles.iter() {
+        let position = build_point(tile.position.0, tile.position.1);
 
-    commands
-        .spawn(BlockFactory::new(1, atlas_handle1, WINDOW_LEFT_X + 1216.0, WINDOW_BOTTOM_Y + 224.0 ));
-    commands
-        .spawn(BlockFactory::new(2, atlas_handle2, WINDOW_LEFT_X + 1712.0, WINDOW_BOTTOM_Y + 176.0 ));
+        commands
+            .spawn(SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(tile.index),
+                texture_atlas: atlas_handle.clone(),
+                transform: Transform {
+                    scale: Vec3::new(SCALE, SCALE, 1.0),
+                    translation: Vec3::new(position.x, position.y, TILE_Z + tile.layer as f32 * TILE_LAYER_Z_STEP),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(LevelEntity);
+    }
 }
 
+fn add_block_to_world(
+    mut commands: Commands,
+    item_atlases: Res<ItemAtlases>,
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+
+    for block in level.blocks.iter() {
+        let position = build_point(block.position.0, block.position.1);
+
+        commands
+            .spawn(BlockFactory::new(block.kind, block.contents, &item_atlases, position.x, position.y))
+            .insert(LevelEntity);
+    }
+}
+
+// Opened bricks look like any other opened block, from the block tileset
 fn apply_opened_block_sprite(
     mut commands: Commands,
+    item_atlases: Res<ItemAtlases>,
     mut query: Query<(
         Entity,
         &Block,
+        &mut Handle<TextureAtlas>,
         &mut TextureAtlasSprite,
     ), With<Opened>>,
 ) {
@@ -181,37 +571,134 @@ fn apply_opened_block_sprite(
         return;
     }
 
-    for (block_entity, block, mut sprite) in query.iter_mut() {
+    for (block_entity, block, mut texture_atlas, mut sprite) in query.iter_mut() {
         commands.entity(block_entity).remove::<Animation>();
+        if *texture_atlas != item_atlases.blocks {
+            *texture_atlas = item_atlases.blocks.clone();
+        }
         sprite.index = SPRITE_IDX_BLOCK_OPENED;
     }
 }
 
-fn add_champi(
+// A hidden block shows up on its first hit, even when it keeps giving coins and isn't opened yet
+fn reveal_hidden_blocks(
+    mut query: Query<&mut Visibility, (With<Block>, Added<Revealed>)>,
+) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn add_goal(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
 ) {
-    let image_handle: Handle<Image> = asset_server.get_handle("spritesheets/tiles.png");
-    let texture_atlas = TextureAtlas::from_grid(
-        image_handle,
-        Vec2::new(SPRITE_TILE_WIDTH, SPRITE_TILE_HEIGHT),
-        4,
-        2,
-        Option::from(Vec2::new(SPRITE_TILE_PADDING, SPRITE_TILE_PADDING_Y)),
-        Option::from(Vec2::new(8.0, 248.0)),
-    );
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
 
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    let Some(goal) = level.goal else { return };
+    let position = build_point(goal.0, goal.1);
 
-    let atlas_handle = atlases.add(texture_atlas);
+    commands
+        .spawn(GoalFactory::new(position.x, position.y))
+        .insert(LevelEntity);
+}
+
+fn add_checkpoints(
+    mut commands: Commands,
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
 
-    let atlas_handle1 = atlas_handle.clone();
-    let atlas_handle2 = atlas_handle.clone();
+    let Some(level) = levels.get(&current_level.handle) else { return };
 
-    commands
-        .spawn(ChampiFactory::new(1, atlas_handle, WINDOW_LEFT_X + 1216.0, WINDOW_BOTTOM_Y + 230.0));
+    for checkpoint in level.checkpoints.iter() {
+        let position = build_point(checkpoint.0, checkpoint.1);
 
-    commands
-        .spawn(ChampiFactory::new(2, atlas_handle1, WINDOW_LEFT_X + 1712.0, WINDOW_BOTTOM_Y + 176.0));
+        commands
+            .spawn(CheckpointFactory::new(position.x, position.y))
+            .insert(LevelEntity);
+    }
 }
 
+fn add_coins(
+    mut commands: Commands,
+    item_atlases: Res<ItemAtlases>,
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+
+    for coin in level.coins.iter() {
+        let position = build_point(coin.0, coin.1);
+
+        commands
+            .spawn(CoinFactory::new(item_atlases.items.clone(), position.x, position.y))
+            .insert(LevelEntity);
+    }
+}
+
+fn add_enemies(
+    mut commands: Commands,
+    enemy_atlases: Res<EnemyAtlases>,
+    mut level_loaded: EventReader<LevelLoaded>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    if level_loaded.is_empty() {
+        return;
+    }
+    level_loaded.clear();
+
+    let Some(level) = levels.get(&current_level.handle) else { return };
+
+    for enemy in level.enemies.iter() {
+        let position = build_point(enemy.position.0, enemy.position.1);
+
+        let mut entity = match enemy.kind {
+            EnemyKind::Goomba => commands.spawn(GoombaFactory::new(enemy_atlases.enemies.clone(), position.x, position.y)),
+            EnemyKind::Koopa => commands.spawn(KoopaFactory::new(enemy_atlases.enemies.clone(), position.x, position.y)),
+        };
+        entity.insert(LevelEntity);
+    }
+}
+
+fn apply_squashed_sprite(
+    mut query: Query<&mut TextureAtlasSprite, Added<Squashed>>,
+) {
+    for mut sprite in query.iter_mut() {
+        sprite.index = SPRITE_IDX_GOOMBA_SQUASHED;
+    }
+}
+
+fn apply_shell_sprite(
+    mut query: Query<&mut TextureAtlasSprite, Added<Shell>>,
+) {
+    for mut sprite in query.iter_mut() {
+        sprite.index = SPRITE_IDX_SHELL;
+    }
+}
+
+fn apply_knocked_out_sprite(
+    mut query: Query<&mut TextureAtlasSprite, Added<KnockedOut>>,
+) {
+    for mut sprite in query.iter_mut() {
+        sprite.flip_y = true;
+    }
+}